@@ -0,0 +1,355 @@
+
+use crate::real::Real;
+
+// Corners of each cube face in counter-clockwise order seen from outside the cube, and the edges
+// from each corner to the next one
+pub const FACES: [([usize; 4], [usize; 4]); 6] = [
+    ([0, 1, 2, 3], [0, 1, 2, 3]),
+    ([4, 7, 6, 5], [7, 6, 5, 4]),
+    ([0, 3, 7, 4], [3, 11, 7, 8]),
+    ([1, 5, 6, 2], [9, 5, 10, 1]),
+    ([0, 4, 5, 1], [8, 4, 9, 0]),
+    ([3, 2, 6, 7], [2, 10, 6, 11]),
+];
+
+// Index of the first vertex placed inside the cube, after the 12 edge vertices. Each piece of
+// surface that can't be triangulated from its edge vertices alone gets one, or three for a tube.
+pub const INNER_VERTEX: usize = 12;
+
+// Closed contours of the iso-surface on the cube surface, as edge indices wound counter-clockwise
+// seen from the outside of the iso-surface. Edges of all loops are stored one loop after another.
+pub struct Loops {
+    pub edges: [usize; 12],
+    pub ends: [usize; 4],
+    pub count: usize,
+}
+
+impl Loops {
+    pub fn trace<T: Real>(g: &[T; 8]) -> Loops {
+        let inside = |c: usize| g[c] < T::zero();
+
+        // On each face, contour segments run from an edge where the corners go from inside to
+        // outside to an edge where they go from outside to inside (counter-clockwise). Links are
+        // stored backwards, from the end of each segment to its start, which makes the loops wind
+        // counter-clockwise around the outward normal of the iso-surface.
+        let mut prev = [None; 12];
+        for &(corners, edges) in &FACES {
+            let crossing = |i: usize| inside(corners[i]) != inside(corners[(i + 1) % 4]);
+            let crossings = (0..4).filter(|&i| crossing(i)).count();
+            // Whether diagonal inside corners of an ambiguous face are joined
+            let joined = crossings == 4 && {
+                let (p02, p13) = (g[corners[0]] * g[corners[2]], g[corners[1]] * g[corners[3]]);
+                if inside(corners[0]) {
+                    p02 > p13
+                } else {
+                    p13 > p02
+                }
+            };
+            for i in 0..4 {
+                if !(inside(corners[i]) && crossing(i)) {
+                    continue;
+                }
+                let end = if crossings == 2 {
+                    (1..4).map(|d| (i + d) % 4).find(|&j| crossing(j)).unwrap()
+                } else if joined {
+                    (i + 1) % 4
+                } else {
+                    (i + 3) % 4
+                };
+                prev[edges[end]] = Some(edges[i]);
+            }
+        }
+
+        let mut loops = Loops {
+            edges: [0; 12],
+            ends: [0; 4],
+            count: 0,
+        };
+        let mut len = 0;
+        let mut visited = [false; 12];
+        for start in 0..12 {
+            if prev[start].is_none() || visited[start] {
+                continue;
+            }
+            let mut e = start;
+            while !visited[e] {
+                visited[e] = true;
+                loops.edges[len] = e;
+                len += 1;
+                e = prev[e].unwrap();
+            }
+            loops.ends[loops.count] = len;
+            loops.count += 1;
+        }
+        loops
+    }
+
+    pub fn start(&self, l: usize) -> usize {
+        if l == 0 {
+            0
+        } else {
+            self.ends[l - 1]
+        }
+    }
+
+    pub fn get(&self, l: usize) -> &[usize] {
+        &self.edges[self.start(l)..self.ends[l]]
+    }
+}
+
+// Triangulates the loops as pieces of surface, where loops with the same key belong to the same
+// piece. Vertices inside the cube are written to `verts` from `INNER_VERTEX` onwards. Returns the
+// number of triangles, which is at most 18.
+pub fn tessellate_pieces<T: Real>(
+    verts: &mut [(T, T, T)],
+    loops: &Loops,
+    keys: &[(usize, usize)],
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let mut tri_count = 0;
+    let mut inner_vertex = INNER_VERTEX;
+    for l in 0..keys.len() {
+        if keys[..l].contains(&keys[l]) {
+            continue;
+        }
+        let mut piece = [0; 4];
+        let mut piece_len = 0;
+        for (m, key) in keys.iter().enumerate().skip(l) {
+            if *key == keys[l] {
+                piece[piece_len] = m;
+                piece_len += 1;
+            }
+        }
+        let piece = &piece[..piece_len];
+        let mut piece_tris = [(0, 0, 0); 18];
+        let piece_tri_count = match *piece {
+            // Disc
+            [l] => tessellate_disc(loops.get(l), &mut piece_tris),
+            // Tunnel between two loops
+            [l0, l1] => {
+                let (a, b) = (loops.get(l0), loops.get(l1));
+                let tube = tessellate_tube(verts, a, b, &mut piece_tris);
+                tube.or_else(|| {
+                    let n = tessellate_ring_tube(verts, a, b, inner_vertex, &mut piece_tris);
+                    inner_vertex += 3;
+                    Some(n)
+                })
+            }
+            _ => None,
+        };
+        if let Some(n) = piece_tri_count {
+            tris[tri_count..(tri_count + n)].copy_from_slice(&piece_tris[..n]);
+            tri_count += n;
+            continue;
+        }
+
+        // Fans around a vertex in the middle of the loops
+        let mut center = (T::zero(), T::zero(), T::zero());
+        let mut n = 0;
+        for &l in piece {
+            for &e in loops.get(l) {
+                let v = verts[e];
+                center = (center.0 + v.0, center.1 + v.1, center.2 + v.2);
+                n += 1;
+            }
+        }
+        let n = T::from_usize(n);
+        verts[inner_vertex] = (center.0 / n, center.1 / n, center.2 / n);
+        for &l in piece {
+            let v = loops.get(l);
+            for (i, &e) in v.iter().enumerate() {
+                tris[tri_count] = (inner_vertex, e, v[(i + 1) % v.len()]);
+                tri_count += 1;
+            }
+        }
+        inner_vertex += 1;
+    }
+    tri_count
+}
+
+// Whether cube edges `e0` and `e1` are on the same face
+fn on_same_face(e0: usize, e1: usize) -> bool {
+    FACES
+        .iter()
+        .any(|&(_, edges)| edges.contains(&e0) && edges.contains(&e1))
+}
+
+// Triangulates a loop as a fan around one of its vertices, which must not be on the same face as
+// any other vertex except its neighbours. Returns the number of triangles, or `None` if there is
+// no such vertex.
+fn tessellate_disc(v: &[usize], tris: &mut [(usize, usize, usize)]) -> Option<usize> {
+    let n = v.len();
+    let first = (0..n).find(|&i| (2..(n - 1)).all(|d| !on_same_face(v[i], v[(i + d) % n])))?;
+    for i in 1..(n - 1) {
+        tris[i - 1] = (v[first], v[(first + i) % n], v[(first + i + 1) % n]);
+    }
+    Some(n - 2)
+}
+
+// Triangulates a tube between loops `a` and `b`. Loop `a` is walked forwards and `b` backwards,
+// starting from the closest pair of vertices, always taking the shorter of the two possible
+// diagonals that isn't on a face of the cube and hasn't been used yet, as the tube would be pinched
+// there. Returns the number of triangles, or `None` if the walk gets stuck.
+fn tessellate_tube<T: Real>(
+    verts: &[(T, T, T)],
+    a: &[usize],
+    b: &[usize],
+    tris: &mut [(usize, usize, usize)],
+) -> Option<usize> {
+    let distance = |i: usize, j: usize| {
+        let (p, q) = (verts[i], verts[j]);
+        (p.0 - q.0) * (p.0 - q.0) + (p.1 - q.1) * (p.1 - q.1) + (p.2 - q.2) * (p.2 - q.2)
+    };
+    let (n, m) = (a.len(), b.len());
+    let s = (0..m)
+        .filter(|&j| !on_same_face(a[0], b[j]))
+        .min_by(|&j, &k| {
+            distance(a[0], b[j])
+                .partial_cmp(&distance(a[0], b[k]))
+                .unwrap()
+        })?;
+    let va = |i: usize| a[i % n];
+    let vb = |j: usize| b[(s + m - j % m) % m];
+    // Diagonals used so far, with the one between the first pair closing the tube at the end
+    let mut used = [(0, 0); 12];
+    let is_allowed = |used: &[(usize, usize)], i: usize, j: usize| {
+        (i, j) == (n, m) || (!used.contains(&(i % n, j % m)) && !on_same_face(va(i), vb(j)))
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        let used_count = i + j + 1;
+        let can_advance_a = i < n && is_allowed(&used[..used_count], i + 1, j);
+        let can_advance_b = j < m && is_allowed(&used[..used_count], i, j + 1);
+        let advance_a = match (can_advance_a, can_advance_b) {
+            (true, true) => distance(va(i + 1), vb(j)) <= distance(va(i), vb(j + 1)),
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => return None,
+        };
+        if advance_a {
+            tris[i + j] = (va(i), va(i + 1), vb(j));
+            i += 1;
+        } else {
+            tris[i + j] = (vb(j + 1), vb(j), va(i));
+            j += 1;
+        }
+        if i + j < used.len() {
+            used[i + j] = (i % n, j % m);
+        }
+    }
+    Some(n + m)
+}
+
+// Triangulates a tube between loops `a` and `b` through a ring of three vertices inside the cube,
+// for loops that `tessellate_tube` can't join without a diagonal on a face. Ring vertices are
+// written to `verts` from `ring` onwards, halfway between the loops and pulled towards the middle
+// of the tube, so no triangle lies on a face. Returns the number of triangles.
+fn tessellate_ring_tube<T: Real>(
+    verts: &mut [(T, T, T)],
+    a: &[usize],
+    b: &[usize],
+    ring: usize,
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let (n, m) = (a.len(), b.len());
+    let zero = (T::zero(), T::zero(), T::zero());
+    let sum = a.iter().chain(b).fold(zero, |s, &e| {
+        let v = verts[e];
+        (s.0 + v.0, s.1 + v.1, s.2 + v.2)
+    });
+    let count = T::from_usize(n + m);
+    let center = (sum.0 / count, sum.1 / count, sum.2 / count);
+
+    // Loops are aligned at the closest pair of vertices and walked in opposite directions, like in
+    // `tessellate_tube`
+    let distance = |i: usize, j: usize| {
+        let (p, q) = (verts[i], verts[j]);
+        (p.0 - q.0) * (p.0 - q.0) + (p.1 - q.1) * (p.1 - q.1) + (p.2 - q.2) * (p.2 - q.2)
+    };
+    let s = (0..m)
+        .min_by(|&j, &k| {
+            distance(a[0], b[j])
+                .partial_cmp(&distance(a[0], b[k]))
+                .unwrap()
+        })
+        .unwrap();
+    let va = |i: usize| a[i % n];
+    let vb = |j: usize| b[(s + m - j % m) % m];
+    let vr = |k: usize| ring + k % 3;
+    let half = T::from_f64(0.5);
+    for k in 0..3 {
+        let (p, q) = (verts[va(k * n / 3)], verts[vb(k * m / 3)]);
+        verts[vr(k)] = (
+            ((p.0 + q.0) * half + center.0) * half,
+            ((p.1 + q.1) * half + center.1) * half,
+            ((p.2 + q.2) * half + center.2) * half,
+        );
+    }
+
+    let tri_count = zip(va, n, vr, 3, tris);
+    tri_count + zip(vr, 3, vb, m, &mut tris[tri_count..])
+}
+
+// Joins `n` vertices given by `va` and `m` vertices given by `vb` into a band of triangles,
+// oriented like the tube in `tessellate_tube`. Both are advanced in proportion to their lengths.
+// Returns the number of triangles.
+fn zip<A, B>(va: A, n: usize, vb: B, m: usize, tris: &mut [(usize, usize, usize)]) -> usize
+where
+    A: Fn(usize) -> usize,
+    B: Fn(usize) -> usize,
+{
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if j == m || (i < n && (2 * i + 1) * m <= (2 * j + 1) * n) {
+            tris[i + j] = (va(i), va(i + 1), vb(j));
+            i += 1;
+        } else {
+            tris[i + j] = (vb(j + 1), vb(j), va(i));
+            j += 1;
+        }
+    }
+    n + m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(edges: &[usize]) -> Vec<usize> {
+        let mut edges = edges.to_vec();
+        edges.sort_unstable();
+        edges
+    }
+
+    #[test]
+    fn test_trace() {
+        let loops = Loops::trace(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(0, loops.count);
+
+        // Single corner inside is cut off by a triangle
+        let loops = Loops::trace(&[-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(1, loops.count);
+        assert_eq!(vec![0, 3, 8], sorted(loops.get(0)));
+
+        // Diagonal corners of the y = 0 face are separate or joined, depending on the saddle
+        let loops = Loops::trace(&[-1.0, 1.0, -1.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+        assert_eq!(2, loops.count);
+        assert_eq!(vec![0, 3, 8], sorted(loops.get(0)));
+        assert_eq!(vec![1, 2, 10], sorted(loops.get(1)));
+        let loops = Loops::trace(&[-2.0, 0.5, -2.0, 0.5, 2.0, 2.0, 2.0, 2.0]);
+        assert_eq!(1, loops.count);
+        assert_eq!(vec![0, 1, 2, 3, 8, 10], sorted(loops.get(0)));
+    }
+
+    #[test]
+    fn test_pieces() {
+        // Loops with the same key are one piece, others are triangulated separately
+        let loops = Loops::trace(&[-1.0, 1.0, -1.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+        let mut verts = [(0.0, 0.0, 0.0); 16];
+        let mut tris = [(0, 0, 0); 18];
+        let tri_count = tessellate_pieces(&mut verts, &loops, &[(0, 0), (1, 0)], &mut tris);
+        assert_eq!(2, tri_count);
+        assert_eq!(vec![0, 3, 8], sorted(&[tris[0].0, tris[0].1, tris[0].2]));
+        assert_eq!(vec![1, 2, 10], sorted(&[tris[1].0, tris[1].1, tris[1].2]));
+    }
+}
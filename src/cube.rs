@@ -1,90 +1,207 @@
 
-use mesh::Triangle;
-use mesh::Vertex;
-use mesh::Mesh;
-
-use tables::VERTS_INSIDE_TO_EDGE_ISECT;
-use tables::EDGE_ISECTS_TO_TRIS;
-use tables::EDGES;
-
-pub fn tessellate_corners(p: &[(f32, f32, f32)], f: &[f32]) -> Mesh {
-    let corners_in = (0..8).filter(|i| f[*i as usize] < 0.0).fold(
-        0,
-        |c, i| c | (1 << i),
-    );
-    let edges = VERTS_INSIDE_TO_EDGE_ISECT[corners_in];
-    if edges == 0 {
-        Mesh::empty()
-    } else {
-        let vmap = vec![
-            edge_intersection_unwrap(edges, 0, p, f),
-            edge_intersection_unwrap(edges, 1, p, f),
-            edge_intersection_unwrap(edges, 2, p, f),
-            edge_intersection_unwrap(edges, 3, p, f),
-            edge_intersection_unwrap(edges, 4, p, f),
-            edge_intersection_unwrap(edges, 5, p, f),
-            edge_intersection_unwrap(edges, 6, p, f),
-            edge_intersection_unwrap(edges, 7, p, f),
-            edge_intersection_unwrap(edges, 8, p, f),
-            edge_intersection_unwrap(edges, 9, p, f),
-            edge_intersection_unwrap(edges, 10, p, f),
-            edge_intersection_unwrap(edges, 11, p, f),
-        ];
+use crate::contour::tessellate_pieces;
+use crate::contour::Loops;
+use crate::contour::FACES;
+use crate::mesh::Normal;
+use crate::real::Real;
 
-        let tri_inds = EDGE_ISECTS_TO_TRIS[corners_in];
+use crate::tables::VERTS_INSIDE_TO_EDGE_ISECT;
+use crate::tables::EDGE_ISECTS_TO_TRIS;
+use crate::tables::EDGES;
 
-        let tris: Vec<_> = tri_inds
-            .iter()
-            .filter_map(|t| if let Some(t) = *t {
-                Some(Triangle(t.0, t.1, t.2))
-            } else {
-                None
-            })
-            .collect();
+// Room in the `verts` and `tris` buffers of one cube that is enough for every tessellation: the 12
+// edge vertices, the vertices inside the cube from `INNER_VERTEX` and the diagonal vertices from
+// `DIAGONAL_VERTEX`, and the largest number of triangles in a cube, which is reached by MC33 tubes
+pub const CUBE_VERT_COUNT: usize = 23;
+pub const CUBE_TRI_COUNT: usize = 18;
 
-        Mesh::new(vmap, tris)
+// Tessellates a cube without allocating. Triangles are written to `tris` as triplets of cube edge
+// indices, and the number of triangles is returned. Intersection of each edge used by the
+// triangles is written to `verts`, indexed by the edge. Other entries of `verts` are left as is.
+//
+// Cubes with an ambiguous face are tessellated with the asymptotic decider instead of the table,
+// so that adjacent cubes agree on the contour along their shared face and the surface has no
+// cracks. Their triangles can use vertices inside the cube, which are written to `verts` from
+// `INNER_VERTEX` onwards. `verts` needs room for `CUBE_VERT_COUNT` vertices and `tris` for
+// `CUBE_TRI_COUNT` triangles, like for the other tessellations.
+pub fn tessellate_corners_into<T: Real>(
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+    verts: &mut [(T, T, T)],
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let corners_in = corners_inside(f, iso);
+    let edges = crossed_edges(corners_in);
+    if edges == 0 {
+        return 0;
+    }
+    if has_ambiguous_face(f, iso) {
+        return tessellate_corners_decider_into(p, f, iso, verts, tris);
+    }
+    for (i, v) in verts.iter_mut().enumerate().take(12) {
+        if let Some(isect) = edge_intersection(edges, i, p, f, iso) {
+            *v = isect;
+        }
     }
+    let mut tri_count = 0;
+    for t in EDGE_ISECTS_TO_TRIS[corners_in].iter().flatten() {
+        tris[tri_count] = *t;
+        tri_count += 1;
+    }
+    tri_count
 }
 
-#[inline]
-fn edge_intersection_unwrap(edges: usize, i: usize, p: &[(f32, f32, f32)], f: &[f32]) -> Vertex {
-    if let Some((x, y, z)) = edge_intersection(edges, i, p, f) {
-        Vertex(x, y, z)
-    } else {
-        Vertex(0.0, 0.0, 0.0)
+// Tessellates a cube like `tessellate_corners_into`, resolving ambiguous faces with the asymptotic
+// decider of Nielson and Hamann but without the interior test of MC33. Each contour on the cube
+// surface is triangulated on its own. Has the same requirements for `verts` and `tris` as
+// `tessellate_corners_into`.
+pub fn tessellate_corners_decider_into<T: Real>(
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+    verts: &mut [(T, T, T)],
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let mut g = [T::zero(); 8];
+    for (g, &f) in g.iter_mut().zip(f) {
+        *g = f - iso;
+    }
+    let loops = Loops::trace(&g);
+    if loops.count == 0 {
+        return 0;
     }
+    for &e in &loops.edges[..loops.ends[loops.count - 1]] {
+        verts[e] = edge_point(e, p, f, iso);
+    }
+    let keys = [(0, 0), (1, 0), (2, 0), (3, 0)];
+    tessellate_pieces(verts, &loops, &keys[..loops.count], tris)
+}
+
+// Whether any face of a cube has its inside corners on one diagonal and outside corners on the
+// other, so the contour on the face depends on how the face is interpolated
+pub fn has_ambiguous_face<T: Real>(f: &[T], iso: T) -> bool {
+    FACES.iter().any(|&(c, _)| {
+        let inside = |i: usize| f[c[i]] < iso;
+        inside(0) == inside(2) && inside(1) == inside(3) && inside(0) != inside(1)
+    })
 }
 
+// Bitmap of the corners of a cube where the field is below `iso`
+pub fn corners_inside<T: Real>(f: &[T], iso: T) -> usize {
+    (0..8).filter(|&i| f[i] < iso).fold(0, |c, i| c | (1 << i))
+}
+
+// Bitmap of the edges of a cube where the field crosses the iso-value, given the corners inside
+// from `corners_inside`
+pub fn crossed_edges(corners_in: usize) -> usize {
+    VERTS_INSIDE_TO_EDGE_ISECT[corners_in]
+}
+
+// Intersection of the iso-surface with edge `i`, or `None` if the edge isn't in `edges` given by
+// `crossed_edges`
 #[inline]
-fn edge_intersection(
+pub fn edge_intersection<T: Real>(
     edges: usize,
     i: usize,
-    p: &[(f32, f32, f32)],
-    f: &[f32],
-) -> Option<(f32, f32, f32)> {
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+) -> Option<(T, T, T)> {
     if (edges >> i) & 1 == 0 {
         None
     } else {
-        let (v0, v1) = EDGES[i];
-        let (p0, p1) = (&p[v0], &p[v1]);
-        let (f0, f1) = (f[v0], f[v1]);
-        if (f0 - f1).abs() < 0.000001 {
-            Some(*p0)
-        } else {
-            Some((
-                p0.0 - f0 * (p1.0 - p0.0) / (f1 - f0),
-                p0.1 - f0 * (p1.1 - p0.1) / (f1 - f0),
-                p0.2 - f0 * (p1.2 - p0.2) / (f1 - f0),
-            ))
-        }
+        Some(edge_point(i, p, f, iso))
     }
 }
 
+// Intersection of the iso-surface with edge `i`, interpolated linearly between its corners
+pub fn edge_point<T: Real>(i: usize, p: &[(T, T, T)], f: &[T], iso: T) -> (T, T, T) {
+    segment_point(EDGES[i], p, f, iso)
+}
+
+// Intersection of the iso-surface with the segment between corners `v0` and `v1`, which can also
+// be a diagonal of the cube
+pub fn segment_point<T: Real>(
+    (v0, v1): (usize, usize),
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+) -> (T, T, T) {
+    let (p0, p1) = (&p[v0], &p[v1]);
+    let (f0, f1) = (f[v0], f[v1]);
+    if (f0 - f1).abs() < T::from_f64(0.000001) {
+        *p0
+    } else {
+        (
+            p0.0 + (iso - f0) * (p1.0 - p0.0) / (f1 - f0),
+            p0.1 + (iso - f0) * (p1.1 - p0.1) / (f1 - f0),
+            p0.2 + (iso - f0) * (p1.2 - p0.2) / (f1 - f0),
+        )
+    }
+}
+
+// Normal of the iso-surface at edge `i`, interpolated from corner gradients `g` the same way as
+// `edge_intersection` interpolates positions.
+pub fn edge_normal<T: Real>(i: usize, g: &[(T, T, T)], f: &[T], iso: T) -> Normal<T> {
+    segment_normal(EDGES[i], g, f, iso)
+}
+
+// Normal of the iso-surface at the intersection given by `segment_point`
+pub fn segment_normal<T: Real>(
+    (v0, v1): (usize, usize),
+    g: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+) -> Normal<T> {
+    let (g0, g1) = (&g[v0], &g[v1]);
+    let (f0, f1) = (f[v0], f[v1]);
+    let n = if (f0 - f1).abs() < T::from_f64(0.000001) {
+        *g0
+    } else {
+        (
+            g0.0 + (iso - f0) * (g1.0 - g0.0) / (f1 - f0),
+            g0.1 + (iso - f0) * (g1.1 - g0.1) / (f1 - f0),
+            g0.2 + (iso - f0) * (g1.2 - g0.2) / (f1 - f0),
+        )
+    };
+    normalize(n)
+}
+
+// Normal of the iso-surface at a vertex inside the cube, from the mean of corner gradients `g`
+pub fn center_normal<T: Real>(g: &[(T, T, T)]) -> Normal<T> {
+    let zero = (T::zero(), T::zero(), T::zero());
+    let n = g.iter().fold(zero, |n, g| (n.0 + g.0, n.1 + g.1, n.2 + g.2));
+    normalize(n)
+}
+
+pub fn normalize<T: Real>(n: (T, T, T)) -> Normal<T> {
+    let len = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
+    if len > T::zero() {
+        Normal(n.0 / len, n.1 / len, n.2 / len)
+    } else {
+        Normal(T::zero(), T::zero(), T::zero())
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::mesh::Mesh;
+    use crate::mesh::Triangle;
+    use crate::mesh::Vertex;
+
+    fn tessellate_corners(p: &[(f32, f32, f32)], f: &[f32], iso: f32) -> Mesh {
+        let mut verts = [(0.0, 0.0, 0.0); CUBE_VERT_COUNT];
+        let mut tris = [(0, 0, 0); CUBE_TRI_COUNT];
+        let tri_count = tessellate_corners_into(p, f, iso, &mut verts, &mut tris);
+        Mesh::new(
+            verts.iter().map(|&(x, y, z)| Vertex(x, y, z)).collect(),
+            tris[..tri_count].iter().map(|&(i0, i1, i2)| Triangle(i0, i1, i2)).collect(),
+        )
+    }
+
     #[test]
     fn test_corners() {
         // an unit cube (0,0,0)-(1,1,1)
@@ -101,7 +218,7 @@ mod tests {
         ];
 
         // Tessellate with isect at YZ plane in middle of the cube
-        let m = tessellate_corners(&p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0]);
+        let m = tessellate_corners(&p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0], 0.0);
         assert_eq!(2, m.1.len()); // tessellates with quad, so 2 triangles
 
         let mut area = None;
@@ -132,7 +249,7 @@ mod tests {
         }
 
         // Tessellate with one corner inside volume
-        let m = tessellate_corners(&p, &[-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        let m = tessellate_corners(&p, &[-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], 0.0);
         assert_eq!(1, m.1.len()); // Intersects 3 edges, so 1 triangle
 
         // Normal should be facing (1,1,1)
@@ -154,6 +271,46 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_ambiguous_face() {
+        let p = [
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (1.0, 0.0, 1.0),
+            (0.0, 0.0, 1.0),
+            (0.0, 1.0, 0.0),
+            (1.0, 1.0, 0.0),
+            (1.0, 1.0, 1.0),
+            (0.0, 1.0, 1.0),
+        ];
+
+        // Corners 0 and 2 are inside, separated on the y = 0 face by the saddle
+        let m = tessellate_corners(&p, &[-1.0, 1.0, -1.0, 1.0, 2.0, 2.0, 2.0, 2.0], 0.0);
+        assert_eq!(2, m.1.len());
+        assert_eq!(2, m.components().len());
+
+        // Same corners joined by the asymptotic decider when they dominate the saddle
+        let m = tessellate_corners(&p, &[-2.0, 0.5, -2.0, 0.5, 2.0, 2.0, 2.0, 2.0], 0.0);
+        assert_eq!(4, m.1.len());
+        assert_eq!(1, m.components().len());
+    }
+
+    #[test]
+    fn test_edge_normal() {
+        let f = [-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0];
+        let mut g = [(1.0, 0.0, 0.0); 8];
+        g[0] = (0.8, 1.2, 0.0);
+        g[1] = (0.8, -1.2, 0.0);
+
+        // Gradients are interpolated to the intersection and normalized
+        assert_eq!(edge_normal(0, &g, &f, 0.0), Normal(1.0, 0.0, 0.0));
+        assert_eq!(edge_normal(2, &g, &f, 0.0), Normal(1.0, 0.0, 0.0));
+
+        let n = edge_normal(0, &g, &f, 0.5);
+        assert!((n.0 - 0.8).abs() < 0.00001);
+        assert!((n.1 + 0.6).abs() < 0.00001);
+    }
+
     #[test]
     fn test_edge_isect() {
         // an unit cube (0,0,0)-(1,1,1)
@@ -172,58 +329,64 @@ mod tests {
         // Intersection is on YZ plane in middle of the unit cube.
         // Test that intersection is found in the middle of the edge (and correct edge is returned.)
         assert_eq!(
-            edge_intersection(1, 0, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0]),
+            edge_intersection(1, 0, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0], 0.0),
             Some((0.5, 0.0, 0.0))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 2, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0]),
+            edge_intersection(0x3ff, 2, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0], 0.0),
             Some((0.5, 0.0, 1.0))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 4, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0]),
+            edge_intersection(0x3ff, 4, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0], 0.0),
             Some((0.5, 1.0, 0.0))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 6, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0]),
+            edge_intersection(0x3ff, 6, &p, &[-1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0], 0.0),
             Some((0.5, 1.0, 1.0))
         );
 
         // Intersection is on XY plane in middle of unit cube
         assert_eq!(
-            edge_intersection(0x3ff, 1, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 1, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0], 0.0),
             Some((1.0, 0.0, 0.5))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 3, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 3, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0], 0.0),
             Some((0.0, 0.0, 0.5))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 5, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 5, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0], 0.0),
             Some((1.0, 1.0, 0.5))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 7, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 7, &p, &[-1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0], 0.0),
             Some((0.0, 1.0, 0.5))
         );
 
         // Intersection is on XZ plane in the middle of unit cube
         assert_eq!(
-            edge_intersection(0x3ff, 8, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 8, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0], 0.0),
             Some((0.0, 0.5, 0.0))
         );
         assert_eq!(
-            edge_intersection(0x3ff, 9, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]),
+            edge_intersection(0x3ff, 9, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0], 0.0),
             Some((1.0, 0.5, 0.0))
         );
         assert_eq!(
-            edge_intersection(0xfff, 10, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]),
+            edge_intersection(0xfff, 10, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0], 0.0),
             Some((1.0, 0.5, 1.0))
         );
         assert_eq!(
-            edge_intersection(0xfff, 11, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]),
+            edge_intersection(0xfff, 11, &p, &[-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0], 0.0),
             Some((0.0, 0.5, 1.0))
         );
 
+        // Same plane, but iso-level shifted to a quarter of the way from bottom to top
+        assert_eq!(
+            edge_intersection(0xfff, 8, &p, &[2.0, 2.0, 2.0, 2.0, 6.0, 6.0, 6.0, 6.0], 3.0),
+            Some((0.0, 0.25, 0.0))
+        );
+
     }
 
 
@@ -0,0 +1,345 @@
+
+use crate::cube::edge_normal;
+use crate::cube::edge_point;
+use crate::cube::normalize;
+use crate::error::Error;
+use crate::field::ScalarField;
+use crate::mesh::Normal;
+use crate::mesh::Vertex;
+use crate::real::Real;
+use crate::surface_nets::create_dual_mesh;
+use crate::surface_nets::DualCell;
+use crate::surface_nets::QuadMesh;
+use crate::tessellator::cube_gradients;
+
+// Singular values of the QEF below this fraction of the largest one are treated as zero, so that
+// flat and edge-like cells keep their vertex near the mass point along the unconstrained
+// directions instead of following noise in the normals
+const SINGULAR_VALUE_CUTOFF: f64 = 0.1;
+
+// Creates a dual contouring mesh of the field, with the same connectivity as `create_surface_net`.
+// The vertex of each cell is placed by minimizing the quadratic error function (QEF) of the
+// tangent planes at the edge intersections, which puts it on sharp edges and corners of the
+// surface. Normals of the planes are interpolated from the central difference gradient of the
+// field. Vertex normals are the mean of the plane normals when `normals` is set. Fails like
+// `create_mesh`.
+pub fn create_dual_contour<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    iso_value: T,
+    normals: bool,
+) -> Result<QuadMesh<T>, Error> {
+    create_dual_mesh(field, min_bound, max_bound, iso_value, |cell| {
+        let g = cube_gradients(field, &cell.p, cell.x, cell.y, cell.z);
+        let normal = |i, _| edge_normal(i, &g, &cell.f, iso_value);
+        place_vertex(cell, iso_value, normals, normal)
+    })
+}
+
+// Same as `create_dual_contour`, but normals of the tangent planes are given by `gradient` of the
+// field at world space positions, e.g. the analytic gradient of the closure the field was sampled
+// from. Finite differences blur the normals next to sharp features, so this places vertices more
+// accurately.
+pub fn create_dual_contour_with_gradient<T, F, G>(
+    field: &F,
+    gradient: G,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    iso_value: T,
+    normals: bool,
+) -> Result<QuadMesh<T>, Error>
+where
+    T: Real,
+    F: ScalarField<T> + ?Sized,
+    G: Fn(T, T, T) -> (T, T, T),
+{
+    create_dual_mesh(field, min_bound, max_bound, iso_value, |cell| {
+        let normal = |_, p: (T, T, T)| normalize(gradient(p.0, p.1, p.2));
+        place_vertex(cell, iso_value, normals, normal)
+    })
+}
+
+// Places the vertex of a cell at the solution of its QEF, with `normal` giving the normal of the
+// tangent plane at the intersection with edge `i`. Also returns a vertex normal when `normals` is
+// set.
+fn place_vertex<T, N>(
+    cell: &DualCell<T>,
+    iso_value: T,
+    normals: bool,
+    normal: N,
+) -> (Vertex<T>, Option<Normal<T>>)
+where
+    T: Real,
+    N: Fn(usize, (T, T, T)) -> Normal<T>,
+{
+    let mut points = [(T::zero(), T::zero(), T::zero()); 12];
+    let mut plane_normals = [(T::zero(), T::zero(), T::zero()); 12];
+    let mut n = 0;
+    for i in (0..12).filter(|&i| (cell.edges >> i) & 1 != 0) {
+        points[n] = edge_point(i, &cell.p, &cell.f, iso_value);
+        let Normal(nx, ny, nz) = normal(i, points[n]);
+        plane_normals[n] = (nx, ny, nz);
+        n += 1;
+    }
+    let (points, plane_normals) = (&points[..n], &plane_normals[..n]);
+
+    let (x, y, z) = solve_qef(points, plane_normals);
+    let vertex = clamp_to_cell(&cell.p, (x, y, z));
+    let normal = if normals {
+        let zero = (T::zero(), T::zero(), T::zero());
+        let sum = plane_normals.iter().fold(zero, |s, n| (s.0 + n.0, s.1 + n.1, s.2 + n.2));
+        Some(normalize(sum))
+    } else {
+        None
+    };
+    (Vertex(vertex.0, vertex.1, vertex.2), normal)
+}
+
+// Point minimizing the sum of squared distances to the planes through `points` with unit
+// `normals`. The system is solved relative to the mass point of `points` with a pseudo-inverse,
+// so along directions the planes don't constrain, the solution stays at the mass point.
+pub fn solve_qef<T: Real>(points: &[(T, T, T)], normals: &[(T, T, T)]) -> (T, T, T) {
+    let count = T::from_usize(points.len());
+    let zero = (T::zero(), T::zero(), T::zero());
+    let sum = points.iter().fold(zero, |s, p| (s.0 + p.0, s.1 + p.1, s.2 + p.2));
+    let c = (sum.0 / count, sum.1 / count, sum.2 / count);
+
+    // Normal equations A x = b of the planes, with x relative to the mass point
+    let mut a = [[T::zero(); 3]; 3];
+    let mut b = [T::zero(); 3];
+    for (p, n) in points.iter().zip(normals) {
+        let n = [n.0, n.1, n.2];
+        let d = n[0] * (p.0 - c.0) + n[1] * (p.1 - c.1) + n[2] * (p.2 - c.2);
+        for i in 0..3 {
+            for j in 0..3 {
+                a[i][j] += n[i] * n[j];
+            }
+            b[i] += n[i] * d;
+        }
+    }
+
+    // A is symmetric and positive semi-definite, so its singular value decomposition is its
+    // eigendecomposition. Its eigenvalues are the squared singular values of the plane equations.
+    let (values, vectors) = symmetric_eigen(a);
+    let max_value = values.iter().fold(T::zero(), |m, &v| m.max(v));
+    let cutoff = max_value.sqrt() * T::from_f64(SINGULAR_VALUE_CUTOFF);
+    let mut x = [T::zero(); 3];
+    for (k, &value) in values.iter().enumerate() {
+        if value <= T::zero() || value.sqrt() <= cutoff {
+            continue;
+        }
+        let v = [vectors[0][k], vectors[1][k], vectors[2][k]];
+        let s = (v[0] * b[0] + v[1] * b[1] + v[2] * b[2]) / value;
+        for i in 0..3 {
+            x[i] += s * v[i];
+        }
+    }
+    (c.0 + x[0], c.1 + x[1], c.2 + x[2])
+}
+
+// Eigenvalues of symmetric matrix `a` and the corresponding eigenvectors as columns, by cyclic
+// Jacobi rotations
+fn symmetric_eigen<T: Real>(mut a: [[T; 3]; 3]) -> ([T; 3], [[T; 3]; 3]) {
+    let (zero, one) = (T::zero(), T::one());
+    let mut v = [[one, zero, zero], [zero, one, zero], [zero, zero, one]];
+    for _ in 0..8 {
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == zero {
+                continue;
+            }
+            // Rotation in the pq-plane that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (a[p][q] + a[p][q]);
+            let t = one / (theta.abs() + (theta * theta + one).sqrt());
+            let t = if theta < zero { -t } else { t };
+            let c = one / (t * t + one).sqrt();
+            let s = t * c;
+            for row in &mut a {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            for k in 0..3 {
+                a[p][k] = c * row_p[k] - s * row_q[k];
+                a[q][k] = s * row_p[k] + c * row_q[k];
+            }
+            for row in &mut v {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+// Clamps `x` to the bounding box of the cell's corners `p`, where the QEF solution can end up
+// when the planes are nearly parallel or the cell only touches a feature
+fn clamp_to_cell<T: Real>(p: &[(T, T, T)], x: (T, T, T)) -> (T, T, T) {
+    let (mut min, mut max) = (p[0], p[0]);
+    for p in &p[1..] {
+        min = (min.0.min(p.0), min.1.min(p.1), min.2.min(p.2));
+        max = (max.0.max(p.0), max.1.max(p.1), max.2.max(p.2));
+    }
+    (
+        x.0.max(min.0).min(max.0),
+        x.1.max(min.1).min(max.1),
+        x.2.max(min.2).min(max.2),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Field;
+    use crate::surface_nets::create_surface_net;
+
+    const HALF_SIZE: f64 = 0.53;
+
+    // Box with half size `HALF_SIZE`, which has sharp edges and corners between grid corners
+    fn cube_field() -> Field<f64> {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        Field::from_fn(
+            |x, y, z| x.abs().max(y.abs()).max(z.abs()) - HALF_SIZE,
+            &min,
+            &max,
+            &(20, 20, 20),
+        )
+    }
+
+    fn cube_gradient(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        if ax >= ay && ax >= az {
+            (x.signum(), 0.0, 0.0)
+        } else if ay >= az {
+            (0.0, y.signum(), 0.0)
+        } else {
+            (0.0, 0.0, z.signum())
+        }
+    }
+
+    fn distance_to_corner(mesh: &QuadMesh<f64>) -> f64 {
+        let c = HALF_SIZE;
+        mesh.0
+            .iter()
+            .map(|v| ((v.0 - c).powi(2) + (v.1 - c).powi(2) + (v.2 - c).powi(2)).sqrt())
+            .fold(f64::MAX, f64::min)
+    }
+
+    #[test]
+    fn test_qef() {
+        // Three planes meet at a point
+        let points = [(1.0, 0.2, 0.3), (0.1, 2.0, 0.4), (0.5, 0.6, 3.0)];
+        let normals = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)];
+        let x = solve_qef(&points, &normals);
+        assert!((x.0 - 1.0).abs() < 1e-12);
+        assert!((x.1 - 2.0).abs() < 1e-12);
+        assert!((x.2 - 3.0).abs() < 1e-12);
+
+        // Two planes meet along a line parallel to z, where the solution stays at the mass point
+        let points = [(1.0, 0.0, 0.0), (0.0, 2.0, 1.0)];
+        let normals = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+        let x = solve_qef(&points, &normals);
+        assert!((x.0 - 1.0).abs() < 1e-12);
+        assert!((x.1 - 2.0).abs() < 1e-12);
+        assert!((x.2 - 0.5).abs() < 1e-12);
+
+        // Nearly parallel planes would meet far away, at y = 3, but are truncated to one plane
+        let s = 0.01f64;
+        let points = [(0.0, 0.0, 0.0), (0.02, 1.0, 0.0)];
+        let normals = [(1.0, 0.0, 0.0), ((1.0 - s * s).sqrt(), s, 0.0)];
+        let x = solve_qef(&points, &normals);
+        assert!(x.0 > 0.0 && x.0 < 0.02 && (x.1 - 0.5).abs() < 0.01);
+
+        // Planes at a larger angle, with a singular value ratio above the cutoff but a squared one
+        // below it, meet where they intersect
+        let s = 0.3f64;
+        let points = [(0.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+        let normals = [(1.0, 0.0, 0.0), ((1.0 - s * s).sqrt(), s, 0.0)];
+        let x = solve_qef(&points, &normals);
+        assert!(x.0.abs() < 1e-9 && (x.1 - 1.0).abs() < 1e-9 && x.2.abs() < 1e-9);
+
+        // Rotated planes through a point
+        let n = |x: f64, y: f64, z: f64| {
+            let l = (x * x + y * y + z * z).sqrt();
+            (x / l, y / l, z / l)
+        };
+        let normals = [n(1.0, 0.3, -0.2), n(-0.4, 1.0, 0.5), n(0.3, 0.2, 1.0), n(1.0, 1.0, 0.0)];
+        let target = (0.25, -0.5, 0.75);
+        let points: Vec<_> = normals
+            .iter()
+            .zip(&[(0.0, 1.0, 1.0), (1.0, 0.0, 2.0), (3.0, 1.0, 0.0), (1.0, -1.0, 0.0)])
+            .map(|(n, t)| {
+                // Point on the plane, moved along the plane from the target
+                let d = t.0 * n.0 + t.1 * n.1 + t.2 * n.2;
+                (target.0 + t.0 - d * n.0, target.1 + t.1 - d * n.1, target.2 + t.2 - d * n.2)
+            })
+            .collect();
+        let x = solve_qef(&points, &normals);
+        assert!((x.0 - target.0).abs() < 1e-9);
+        assert!((x.1 - target.1).abs() < 1e-9);
+        assert!((x.2 - target.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let p = [
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (1.0, 0.0, 1.0),
+            (0.0, 0.0, 1.0),
+            (0.0, 1.0, 0.0),
+            (1.0, 1.0, 0.0),
+            (1.0, 1.0, 1.0),
+            (0.0, 1.0, 1.0),
+        ];
+        assert_eq!((1.0, 0.5, 0.0), clamp_to_cell(&p, (3.0, 0.5, -1.0)));
+    }
+
+    #[test]
+    fn test_sharp_corner() {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let field = cube_field();
+        let mesh = create_dual_contour_with_gradient(&field, cube_gradient, &min, &max, 0.0, true);
+        let mesh = mesh.unwrap();
+        assert_eq!(mesh.0.len(), mesh.2.len());
+        assert!(mesh.triangulate().is_closed());
+
+        // Every vertex is on the box, and one is on its corner
+        for v in &mesh.0 {
+            assert!((v.0.abs().max(v.1.abs()).max(v.2.abs()) - HALF_SIZE).abs() < 1e-9);
+        }
+        assert!(distance_to_corner(&mesh) < 1e-9);
+
+        // Surface nets rounds the corner off
+        let net = create_surface_net(&field, &min, &max, 0.0, true).unwrap();
+        assert!(distance_to_corner(&net) > 0.03);
+
+        // Finite differences blur the normals around the corner, but still get closer to it
+        let mesh = create_dual_contour(&field, &min, &max, 0.0, true).unwrap();
+        assert!(mesh.triangulate().is_closed());
+        assert!(distance_to_corner(&mesh) < distance_to_corner(&net));
+    }
+
+    #[test]
+    fn test_sphere() {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let field = Field::from_fn(
+            |x: f64, y: f64, z: f64| (x * x + y * y + z * z).sqrt() - 0.8,
+            &min,
+            &max,
+            &(24, 24, 24),
+        );
+        let gradient = |x: f64, y: f64, z: f64| (x, y, z);
+        let mesh = create_dual_contour_with_gradient(&field, gradient, &min, &max, 0.0, false);
+        let mesh = mesh.unwrap().triangulate();
+        assert!(mesh.is_closed());
+        for (t, n) in mesh.1.iter().zip(mesh.face_normals()) {
+            let v = &mesh.0[t.0];
+            assert!(((v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt() - 0.8).abs() < 0.01);
+            assert!(v.0 * n.0 + v.1 * n.1 + v.2 * n.2 > 0.0);
+        }
+    }
+}
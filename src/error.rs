@@ -0,0 +1,64 @@
+
+use std::error;
+use std::fmt;
+use std::io;
+
+// Reasons for rejecting field data or meshing parameters
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    // Field has no data
+    EmptyField,
+    // Rows or slices of nested vectors have different lengths
+    RaggedField,
+    // Field has less than two corners along some axis, so there are no cubes to tessellate
+    TooFewCorners { corner_count: (usize, usize, usize) },
+    // Total number of corners doesn't fit in `usize`
+    TooManyCorners { corner_count: (usize, usize, usize) },
+    // Length of flat data doesn't match the dimensions of the field
+    SizeMismatch { expected: usize, actual: usize },
+    // Field value at the given index of the flat data is NaN
+    NotANumber { index: usize },
+    // `min_bound` isn't less than `max_bound` along every axis
+    InvalidBounds,
+    // Field places its grid so that cubes have no volume, e.g. with zero spacing
+    DegenerateGrid,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::EmptyField => write!(f, "Field is empty"),
+            Error::RaggedField => write!(f, "Rows of the field have different lengths"),
+            Error::TooFewCorners { corner_count } => write!(
+                f,
+                "Field needs at least two corners along each axis, got {:?}",
+                corner_count
+            ),
+            Error::TooManyCorners { corner_count } => {
+                write!(f, "Field of {:?} corners is too large", corner_count)
+            }
+            Error::SizeMismatch { expected, actual } => write!(
+                f,
+                "Field data length {} doesn't match dimensions, expected {}",
+                actual, expected
+            ),
+            Error::NotANumber { index } => write!(f, "Field value at index {} is NaN", index),
+            Error::InvalidBounds => write!(f, "Minimum bound must be less than maximum bound"),
+            Error::DegenerateGrid => write!(f, "Grid of the field has no volume"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// Invalid data in a file is reported by the loaders as `io::ErrorKind::InvalidData`
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+// Error for invalid data in a file, with a message saying what is wrong
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
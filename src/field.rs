@@ -1,12 +1,82 @@
 
+use crate::error::Error;
+use crate::real::Real;
 
-pub struct Field(Vec<Vec<Vec<f32>>>);
+// Source of scalar values sampled at corners of a regular grid of cubes.
+pub trait ScalarField<T: Real = f32> {
+    fn corner_count(&self) -> (usize, usize, usize);
+    fn f(&self, x: usize, y: usize, z: usize) -> T;
 
-impl Field {
-    pub fn from_vecs(vecs: Vec<Vec<Vec<f32>>>) -> Field {
-        Field(vecs)
+    fn cube_count(&self) -> (usize, usize, usize) {
+        let c = self.corner_count();
+        (c.0.saturating_sub(1), c.1.saturating_sub(1), c.2.saturating_sub(1))
+    }
+
+    // Gradient at a grid corner in grid units (i.e. distance between adjacent corners is 1).
+    // Central differences are used inside the grid and one-sided differences at its boundary.
+    fn gradient(&self, x: usize, y: usize, z: usize) -> (T, T, T) {
+        let c = self.corner_count();
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(c.0 - 1));
+        let (y0, y1) = (y.saturating_sub(1), (y + 1).min(c.1 - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(c.2 - 1));
+        (
+            (self.f(x1, y, z) - self.f(x0, y, z)) / T::from_usize(x1 - x0),
+            (self.f(x, y1, z) - self.f(x, y0, z)) / T::from_usize(y1 - y0),
+            (self.f(x, y, z1) - self.f(x, y, z0)) / T::from_usize(z1 - z0),
+        )
+    }
+
+    // World-space position of a grid corner. Fields that don't define their own placement return
+    // `None`, and are spread evenly between the bounds given to `create_mesh`. A field has to place
+    // either all of its corners or none of them.
+    fn position(&self, _x: usize, _y: usize, _z: usize) -> Option<(T, T, T)> {
+        None
+    }
+}
+
+// Scalar values at grid corners, stored in one contiguous buffer. Values are laid out x-major:
+// index of corner (x, y, z) is x + y * corner_count.0 + z * corner_count.0 * corner_count.1.
+//
+// A field can also carry its placement in world space, set with `with_origin`, `with_spacing` and
+// `with_transform`. Corner (x, y, z) is then at `transform * (origin + (x, y, z) * spacing)`, and
+// the bounds given to `create_mesh` are ignored.
+pub struct Field<T = f32> {
+    dims: (usize, usize, usize),
+    data: Vec<T>,
+    placement: Option<Placement<T>>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Placement<T> {
+    origin: (T, T, T),
+    spacing: (T, T, T),
+    // Rows of a 3x4 affine matrix, applied to positions in the grid's own frame
+    transform: Option<[[T; 4]; 3]>,
+}
+
+impl<T: Real> Placement<T> {
+    fn position(&self, x: usize, y: usize, z: usize) -> (T, T, T) {
+        let p = [
+            self.origin.0 + T::from_usize(x) * self.spacing.0,
+            self.origin.1 + T::from_usize(y) * self.spacing.1,
+            self.origin.2 + T::from_usize(z) * self.spacing.2,
+        ];
+        match self.transform {
+            Some(ref m) => {
+                let row = |r: &[T; 4]| r[0] * p[0] + r[1] * p[1] + r[2] * p[2] + r[3];
+                (row(&m[0]), row(&m[1]), row(&m[2]))
+            }
+            None => (p[0], p[1], p[2]),
+        }
     }
+}
 
+impl Field {
+    // Samples `field` at grid corners spread evenly between the bounds. The field is placed between
+    // the same bounds as with `with_bounds`, so the bounds given to `create_mesh` are ignored and
+    // the mesh is where the closure was sampled. Earlier versions stretched the field to the
+    // `create_mesh` bounds instead; call `with_bounds` to place it elsewhere. For other scalar
+    // types than `f32`, see `Field::from_fn`.
     pub fn from_closure<F>(
         field: F,
         min_bound: &(f32, f32, f32),
@@ -15,40 +85,435 @@ impl Field {
     ) -> Field
     where
         F: Fn(f32, f32, f32) -> f32,
+    {
+        Field::from_fn(field, min_bound, max_bound, cube_count)
+    }
+}
+
+impl<T: Real> Field<T> {
+    // Same as `Field::try_from_flat`, but panics on invalid data
+    pub fn from_flat(dims: (usize, usize, usize), data: Vec<T>) -> Field<T> {
+        Field::try_from_flat(dims, data).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Same as `Field::try_from_vecs`, but panics on invalid data
+    pub fn from_vecs(vecs: Vec<Vec<Vec<T>>>) -> Field<T> {
+        Field::try_from_vecs(vecs).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Same as `Field::from_closure`, for any scalar type. Panics if the closure returns NaN, if
+    // any component of `cube_count` is zero, or if the bounds aren't increasing along every axis.
+    pub fn from_fn<F>(
+        field: F,
+        min_bound: &(T, T, T),
+        max_bound: &(T, T, T),
+        cube_count: &(usize, usize, usize),
+    ) -> Field<T>
+    where
+        F: Fn(T, T, T) -> T,
+    {
+        Field::try_from_fn(field, min_bound, max_bound, cube_count)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Field of `dims` corners from values in x-major order. Data must have exactly one value for
+    // each corner, at least two corners along each axis, and no NaNs.
+    pub fn try_from_flat(dims: (usize, usize, usize), data: Vec<T>) -> Result<Field<T>, Error> {
+        if data.is_empty() {
+            return Err(Error::EmptyField);
+        }
+        if dims.0 < 2 || dims.1 < 2 || dims.2 < 2 {
+            return Err(Error::TooFewCorners { corner_count: dims });
+        }
+        let expected =
+            total_corner_count(dims).ok_or(Error::TooManyCorners { corner_count: dims })?;
+        if data.len() != expected {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        if let Some(index) = data.iter().position(|v| v.is_nan()) {
+            return Err(Error::NotANumber { index });
+        }
+        Ok(Field {
+            dims,
+            data,
+            placement: None,
+        })
+    }
+
+    // Field from values indexed as `vecs[z][y][x]`. All rows and slices must have the same length.
+    pub fn try_from_vecs(vecs: Vec<Vec<Vec<T>>>) -> Result<Field<T>, Error> {
+        let dims = match vecs.first().and_then(|slice| slice.first()) {
+            Some(row) => (row.len(), vecs[0].len(), vecs.len()),
+            None => return Err(Error::EmptyField),
+        };
+        let mut data = Vec::new();
+        for slice in vecs {
+            if slice.len() != dims.1 {
+                return Err(Error::RaggedField);
+            }
+            for row in slice {
+                if row.len() != dims.0 {
+                    return Err(Error::RaggedField);
+                }
+                data.extend(row);
+            }
+        }
+        Field::try_from_flat(dims, data)
+    }
+
+    // Samples `field` at grid corners spread evenly between the bounds, and places the field
+    // between them. Fails if the closure returns NaN, if any component of `cube_count` is zero, or
+    // if the bounds aren't increasing along every axis.
+    pub fn try_from_fn<F>(
+        field: F,
+        min_bound: &(T, T, T),
+        max_bound: &(T, T, T),
+        cube_count: &(usize, usize, usize),
+    ) -> Result<Field<T>, Error>
+    where
+        F: Fn(T, T, T) -> T,
     {
         let corner_counts = (cube_count.0 + 1, cube_count.1 + 1, cube_count.2 + 1);
-        let mut field_table = Vec::with_capacity(corner_counts.0);
+        if cube_count.0 == 0 || cube_count.1 == 0 || cube_count.2 == 0 {
+            return Err(Error::TooFewCorners {
+                corner_count: corner_counts,
+            });
+        }
+        validate_bounds(min_bound, max_bound)?;
+        let len = total_corner_count(corner_counts).ok_or(Error::TooManyCorners {
+            corner_count: corner_counts,
+        })?;
+        let mut data = Vec::with_capacity(len);
         for z in 0..corner_counts.2 {
-            let mut slice = Vec::with_capacity(corner_counts.1);
             for y in 0..corner_counts.1 {
-                let mut row = Vec::with_capacity(corner_counts.2);
                 for x in 0..corner_counts.0 {
-                    let (fx, fy, fz) = (x as f32, y as f32, z as f32);
-                    let fp = (
-                        min_bound.0 + fx * (max_bound.0 - min_bound.0) / (cube_count.0 as f32),
-                        min_bound.1 + fy * (max_bound.1 - min_bound.1) / (cube_count.1 as f32),
-                        min_bound.2 + fz * (max_bound.2 - min_bound.2) / (cube_count.2 as f32),
-                    );
-                    row.push(field(fp.0, fp.1, fp.2));
+                    let fp = grid_position(min_bound, max_bound, cube_count, x, y, z);
+                    data.push(field(fp.0, fp.1, fp.2));
                 }
-                slice.push(row)
             }
-            field_table.push(slice);
         }
-        Field(field_table)
+        let bounds = Bounds {
+            min: *min_bound,
+            max: *max_bound,
+        };
+        Ok(Field::try_from_flat(corner_counts, data)?.with_bounds(&bounds))
     }
 
     pub fn cube_count(&self) -> (usize, usize, usize) {
-        (
-            self.0[0][0].len() - 1,
-            self.0[0].len() - 1,
-            self.0.len() - 1,
-        )
+        (self.dims.0 - 1, self.dims.1 - 1, self.dims.2 - 1)
     }
     pub fn corner_count(&self) -> (usize, usize, usize) {
-        (self.0[0][0].len(), self.0[0].len(), self.0.len())
+        self.dims
+    }
+    pub fn f(&self, x: usize, y: usize, z: usize) -> T {
+        self.data[x + self.dims.0 * (y + self.dims.1 * z)]
+    }
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
     }
-    pub fn f(&self, x: usize, y: usize, z: usize) -> f32 {
-        self.0[z][y][x]
+
+    // Places corner (0, 0, 0) of the grid at `origin`, before the transform (if any) is applied
+    pub fn with_origin(mut self, origin: (T, T, T)) -> Field<T> {
+        self.placement_mut().origin = origin;
+        self
+    }
+
+    // Distance between adjacent corners along each axis, before the transform (if any) is applied
+    pub fn with_spacing(mut self, spacing: (T, T, T)) -> Field<T> {
+        self.placement_mut().spacing = spacing;
+        self
+    }
+
+    // Affine transform from the grid's own frame to world space, given as rows of a 3x4 matrix
+    // whose last column is the translation. Useful for oblique volumes, whose axes aren't aligned
+    // with world axes.
+    pub fn with_transform(mut self, transform: [[T; 4]; 3]) -> Field<T> {
+        self.placement_mut().transform = Some(transform);
+        self
+    }
+
+    // Spreads the grid evenly between the bounds, like `create_mesh` does for unplaced fields
+    pub fn with_bounds(self, bounds: &Bounds<T>) -> Field<T> {
+        let c = self.cube_count();
+        let spacing = (
+            (bounds.max.0 - bounds.min.0) / T::from_usize(c.0),
+            (bounds.max.1 - bounds.min.1) / T::from_usize(c.1),
+            (bounds.max.2 - bounds.min.2) / T::from_usize(c.2),
+        );
+        self.with_origin(bounds.min).with_spacing(spacing)
+    }
+
+    pub fn origin(&self) -> Option<(T, T, T)> {
+        self.placement.as_ref().map(|p| p.origin)
+    }
+    pub fn spacing(&self) -> Option<(T, T, T)> {
+        self.placement.as_ref().map(|p| p.spacing)
+    }
+    pub fn transform(&self) -> Option<&[[T; 4]; 3]> {
+        self.placement.as_ref().and_then(|p| p.transform.as_ref())
+    }
+
+    // Smallest axis-aligned box containing the corners of a placed field in world space
+    pub fn bounds(&self) -> Option<Bounds<T>> {
+        let placement = self.placement.as_ref()?;
+        let (cx, cy, cz) = self.cube_count();
+        let mut bounds = Bounds {
+            min: placement.position(0, 0, 0),
+            max: placement.position(0, 0, 0),
+        };
+        for &(x, y, z) in &[
+            (cx, 0, 0),
+            (0, cy, 0),
+            (cx, cy, 0),
+            (0, 0, cz),
+            (cx, 0, cz),
+            (0, cy, cz),
+            (cx, cy, cz),
+        ] {
+            let p = placement.position(x, y, z);
+            bounds.min = (bounds.min.0.min(p.0), bounds.min.1.min(p.1), bounds.min.2.min(p.2));
+            bounds.max = (bounds.max.0.max(p.0), bounds.max.1.max(p.1), bounds.max.2.max(p.2));
+        }
+        Some(bounds)
+    }
+
+    fn placement_mut(&mut self) -> &mut Placement<T> {
+        self.placement.get_or_insert_with(|| Placement {
+            origin: (T::zero(), T::zero(), T::zero()),
+            spacing: (T::one(), T::one(), T::one()),
+            transform: None,
+        })
+    }
+}
+
+impl<T: Real> ScalarField<T> for Field<T> {
+    fn corner_count(&self) -> (usize, usize, usize) {
+        self.dims
+    }
+    fn f(&self, x: usize, y: usize, z: usize) -> T {
+        self.data[x + self.dims.0 * (y + self.dims.1 * z)]
+    }
+    fn position(&self, x: usize, y: usize, z: usize) -> Option<(T, T, T)> {
+        self.placement.as_ref().map(|p| p.position(x, y, z))
+    }
+}
+
+// World-space bounds of a field's grid, e.g. as given in the header of a volume file. Can be
+// passed to `create_mesh` as `&bounds.min, &bounds.max`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bounds<T = f32> {
+    pub min: (T, T, T),
+    pub max: (T, T, T),
+}
+
+impl<T: Real> Bounds<T> {
+    // Bounds of a grid of `corner_count` corners starting at `origin`, with `spacing` between
+    // adjacent corners
+    pub fn from_spacing(
+        origin: (T, T, T),
+        spacing: (T, T, T),
+        corner_count: (usize, usize, usize),
+    ) -> Bounds<T> {
+        Bounds {
+            min: origin,
+            max: (
+                origin.0 + spacing.0 * T::from_usize(corner_count.0 - 1),
+                origin.1 + spacing.1 * T::from_usize(corner_count.1 - 1),
+                origin.2 + spacing.2 * T::from_usize(corner_count.2 - 1),
+            ),
+        }
+    }
+}
+
+// Fails unless `min_bound` is less than `max_bound` along every axis
+pub fn validate_bounds<T: Real>(
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+) -> Result<(), Error> {
+    if min_bound.0 < max_bound.0 && min_bound.1 < max_bound.1 && min_bound.2 < max_bound.2 {
+        Ok(())
+    } else {
+        Err(Error::InvalidBounds)
+    }
+}
+
+// Transform placing the grid axes along unit vectors `axes`, with corner (0, 0, 0) at `origin`.
+// Used for volumes whose axes are given as direction vectors, as in medical image headers.
+pub fn axes_transform<T: Real>(axes: &[(T, T, T); 3], origin: (T, T, T)) -> [[T; 4]; 3] {
+    [
+        [axes[0].0, axes[1].0, axes[2].0, origin.0],
+        [axes[0].1, axes[1].1, axes[2].1, origin.1],
+        [axes[0].2, axes[1].2, axes[2].2, origin.2],
+    ]
+}
+
+// Number of corners in a grid of `dims` corners, or `None` if it overflows. Dimensions read from
+// files should be checked with this before allocating anything for the samples.
+pub fn total_corner_count(dims: (usize, usize, usize)) -> Option<usize> {
+    dims.0.checked_mul(dims.1)?.checked_mul(dims.2)
+}
+
+// Position of grid corner (x, y, z), where corners are spread evenly between the bounds. This is
+// where `Field::from_closure` samples its closure.
+pub fn grid_position<T: Real>(
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    cube_count: &(usize, usize, usize),
+    x: usize,
+    y: usize,
+    z: usize,
+) -> (T, T, T) {
+    let (fx, fy, fz) = (T::from_usize(x), T::from_usize(y), T::from_usize(z));
+    (
+        min_bound.0 + fx * (max_bound.0 - min_bound.0) / T::from_usize(cube_count.0),
+        min_bound.1 + fy * (max_bound.1 - min_bound.1) / T::from_usize(cube_count.1),
+        min_bound.2 + fz * (max_bound.2 - min_bound.2) / T::from_usize(cube_count.2),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_layout() {
+        let f = Field::from_flat((2, 3, 4), (0..24).map(|i| i as f32).collect());
+        assert_eq!(f.corner_count(), (2, 3, 4));
+        assert_eq!(f.cube_count(), (1, 2, 3));
+        assert_eq!(f.f(0, 0, 0), 0.0);
+        assert_eq!(f.f(1, 0, 0), 1.0);
+        assert_eq!(f.f(0, 1, 0), 2.0);
+        assert_eq!(f.f(0, 0, 1), 6.0);
+        assert_eq!(f.f(1, 2, 3), 23.0);
+
+        let v = Field::from_vecs(vec![
+            vec![vec![0.0, 1.0], vec![2.0, 3.0]],
+            vec![vec![4.0, 5.0], vec![6.0, 7.0]],
+        ]);
+        assert_eq!(v.as_slice(), &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn test_gradient() {
+        // f = x + 2y + 3z
+        let f = Field::from_flat(
+            (3, 3, 3),
+            (0..27)
+                .map(|i| ((i % 3) + 2 * ((i / 3) % 3) + 3 * (i / 9)) as f32)
+                .collect(),
+        );
+        assert_eq!(f.gradient(1, 1, 1), (1.0, 2.0, 3.0));
+        assert_eq!(f.gradient(0, 0, 0), (1.0, 2.0, 3.0));
+        assert_eq!(f.gradient(2, 2, 2), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let f = Field::<f64>::from_fn(
+            |x, y, z| x + 10.0 * y + 100.0 * z,
+            &(0.0, 0.0, 0.0),
+            &(1.0, 2.0, 3.0),
+            &(1, 2, 3),
+        );
+        assert_eq!(f.corner_count(), (2, 3, 4));
+        assert_eq!(f.f(1, 2, 3), 321.0);
+
+        // Field is placed where it was sampled
+        assert_eq!(Some((1.0, 1.0, 1.0)), f.position(1, 1, 1));
+        let bounds = f.bounds().unwrap();
+        assert_eq!((0.0, 0.0, 0.0), bounds.min);
+        assert_eq!((1.0, 2.0, 3.0), bounds.max);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let ragged = vec![
+            vec![vec![0.0, 1.0], vec![2.0]],
+            vec![vec![4.0, 5.0], vec![6.0, 7.0]],
+        ];
+        assert_eq!(Some(Error::RaggedField), Field::try_from_vecs(ragged).err());
+        let ragged = vec![vec![vec![0.0, 1.0], vec![2.0, 3.0]], vec![vec![4.0, 5.0]]];
+        assert_eq!(Some(Error::RaggedField), Field::try_from_vecs(ragged).err());
+        let empty: Vec<Vec<Vec<f32>>> = vec![];
+        assert_eq!(Some(Error::EmptyField), Field::try_from_vecs(empty).err());
+        assert_eq!(
+            Some(Error::EmptyField),
+            Field::<f32>::try_from_vecs(vec![vec![]]).err()
+        );
+        let single_layer = vec![vec![vec![0.0, 1.0], vec![2.0, 3.0]]];
+        assert_eq!(
+            Some(Error::TooFewCorners {
+                corner_count: (2, 2, 1)
+            }),
+            Field::try_from_vecs(single_layer).err()
+        );
+
+        assert_eq!(
+            Some(Error::SizeMismatch {
+                expected: 8,
+                actual: 7
+            }),
+            Field::try_from_flat((2, 2, 2), vec![0.0; 7]).err()
+        );
+        assert_eq!(
+            Some(Error::TooManyCorners {
+                corner_count: (usize::MAX, 2, 2)
+            }),
+            Field::try_from_flat((usize::MAX, 2, 2), vec![0.0; 8]).err()
+        );
+        let mut data = vec![0.0; 8];
+        data[5] = f32::NAN;
+        assert_eq!(
+            Some(Error::NotANumber { index: 5 }),
+            Field::try_from_flat((2, 2, 2), data).err()
+        );
+
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let result = Field::try_from_fn(|x: f32, _, _| x.sqrt() - 0.5, &min, &max, &(2, 2, 2));
+        assert!(result.is_ok());
+        let result = Field::try_from_fn(|x: f32, _, _| (x - 0.5).sqrt(), &min, &max, &(2, 2, 2));
+        assert_eq!(Some(Error::NotANumber { index: 0 }), result.err());
+        let result = Field::try_from_fn(|x: f32, _, _| x, &min, &max, &(2, 0, 2));
+        assert!(result.is_err());
+        let result = Field::try_from_fn(|x: f32, _, _| x, &max, &min, &(2, 2, 2));
+        assert_eq!(Some(Error::InvalidBounds), result.err());
+    }
+
+    #[test]
+    fn test_placement() {
+        let f = Field::from_flat((2, 3, 4), vec![0.0; 24]);
+        assert_eq!(None, f.position(1, 1, 1));
+        assert_eq!(None, f.bounds());
+
+        let f = f.with_origin((1.0, 2.0, 3.0)).with_spacing((0.5, 1.0, 2.0));
+        assert_eq!(Some((1.5, 3.0, 5.0)), f.position(1, 1, 1));
+        let bounds = f.bounds().unwrap();
+        assert_eq!((1.0, 2.0, 3.0), bounds.min);
+        assert_eq!((1.5, 4.0, 9.0), bounds.max);
+
+        // Rotation by 90 degrees around z, then translation by (10, 0, 0)
+        let axes = [(0.0, 1.0, 0.0), (-1.0, 0.0, 0.0), (0.0, 0.0, 1.0)];
+        let f = f.with_transform(axes_transform(&axes, (10.0, 0.0, 0.0)));
+        assert_eq!(Some((7.0, 1.5, 5.0)), f.position(1, 1, 1));
+        let bounds = f.bounds().unwrap();
+        assert_eq!((6.0, 1.0, 3.0), bounds.min);
+        assert_eq!((8.0, 1.5, 9.0), bounds.max);
+
+        let bounds = Bounds {
+            min: (-1.0, -1.0, 0.0),
+            max: (1.0, 3.0, 1.5),
+        };
+        let f = Field::from_flat((2, 3, 4), vec![0.0; 24]).with_bounds(&bounds);
+        assert_eq!(Some(bounds), f.bounds());
+        assert_eq!(Some((2.0, 2.0, 0.5)), f.spacing());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_flat_size_mismatch() {
+        Field::from_flat((2, 2, 2), vec![0.0; 7]);
     }
 }
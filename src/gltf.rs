@@ -0,0 +1,260 @@
+
+use std::io;
+use std::io::Write;
+
+use crate::mesh::Mesh;
+use crate::real::Real;
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const CHUNK_JSON: u32 = 0x4e4f_534a; // "JSON"
+const CHUNK_BIN: u32 = 0x004e_4942; // "BIN\0"
+
+// Component types and buffer view targets from the glTF 2.0 specification
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+impl<T: Real> Mesh<T> {
+    // Writes the mesh as a self-contained binary glTF 2.0 (.glb) file with a single node. Positions
+    // and normals (if any) are stored as `f32` and triangle indices as `u32`, so larger indices
+    // fail with `InvalidInput`. Empty meshes can't be written, as glTF doesn't allow empty
+    // accessors.
+    pub fn write_glb<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if self.1.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Can't write an empty mesh as glTF",
+            ));
+        }
+
+        // Binary buffer: positions, normals, indices
+        let mut bin = Vec::new();
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for v in &self.0 {
+            for (i, &c) in [v.0, v.1, v.2].iter().enumerate() {
+                let c = c.to_f64() as f32;
+                min[i] = min[i].min(c);
+                max[i] = max[i].max(c);
+                bin.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        let positions_len = bin.len();
+        for n in &self.2 {
+            for &c in &[n.0, n.1, n.2] {
+                bin.extend_from_slice(&(c.to_f64() as f32).to_le_bytes());
+            }
+        }
+        let normals_len = bin.len() - positions_len;
+        for t in &self.1 {
+            for &i in &[t.0, t.1, t.2] {
+                let i = u32::try_from(i).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Vertex index too large for glTF",
+                    )
+                })?;
+                bin.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+        let indices_len = bin.len() - positions_len - normals_len;
+
+        let vert_count = self.0.len();
+        let mut views = vec![buffer_view(0, positions_len, ARRAY_BUFFER)];
+        let mut accessors = vec![format!(
+            "{{\"bufferView\":0,\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\
+             \"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+            FLOAT, vert_count, min[0], min[1], min[2], max[0], max[1], max[2]
+        )];
+        let mut attributes = "\"POSITION\":0".to_string();
+        if self.has_normals() {
+            views.push(buffer_view(positions_len, normals_len, ARRAY_BUFFER));
+            accessors.push(format!(
+                "{{\"bufferView\":1,\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}",
+                FLOAT, vert_count
+            ));
+            attributes += ",\"NORMAL\":1";
+        }
+        views.push(buffer_view(
+            positions_len + normals_len,
+            indices_len,
+            ELEMENT_ARRAY_BUFFER,
+        ));
+        accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+            views.len() - 1,
+            UNSIGNED_INT,
+            3 * self.1.len()
+        ));
+
+        let json = format!(
+            "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"marching-cubes\"}},\
+             \"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],\"nodes\":[{{\"mesh\":0}}],\
+             \"meshes\":[{{\"primitives\":[{{\"attributes\":{{{}}},\"indices\":{},\"mode\":4}}]}}],\
+             \"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{}}}]}}",
+            attributes,
+            accessors.len() - 1,
+            accessors.join(","),
+            views.join(","),
+            bin.len()
+        );
+
+        // Chunks are padded to 4 bytes, JSON with spaces and binary data with zeros
+        let mut json = json.into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let total_len = 12 + 8 + json.len() + 8 + bin.len();
+        for &word in &[GLB_MAGIC, 2, total_len as u32] {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        for &(chunk_type, data) in &[(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            w.write_all(&chunk_type.to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+fn buffer_view(offset: usize, len: usize, target: u32) -> String {
+    format!(
+        "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+        offset, len, target
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value;
+
+    use crate::mesh::Triangle;
+    use crate::mesh::Vertex;
+    use crate::tessellator::tests::sphere_mesh;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    }
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_bits(u32_at(bytes, offset))
+    }
+
+    // Checks the container layout and returns the JSON and binary chunks
+    fn parse_glb(glb: &[u8]) -> (Value, &[u8]) {
+        assert_eq!(GLB_MAGIC, u32_at(glb, 0));
+        assert_eq!(2, u32_at(glb, 4));
+        assert_eq!(glb.len(), u32_at(glb, 8) as usize);
+
+        let json_len = u32_at(glb, 12) as usize;
+        assert_eq!(0, json_len % 4);
+        assert_eq!(CHUNK_JSON, u32_at(glb, 16));
+        let json = serde_json::from_slice(&glb[20..(20 + json_len)]).unwrap();
+
+        let bin_start = 20 + json_len;
+        let bin_len = u32_at(glb, bin_start) as usize;
+        assert_eq!(0, bin_len % 4);
+        assert_eq!(CHUNK_BIN, u32_at(glb, bin_start + 4));
+        assert_eq!(glb.len(), bin_start + 8 + bin_len);
+        (json, &glb[(bin_start + 8)..])
+    }
+
+    // Checks that accessors fit in their buffer views, and views in the buffer
+    fn check_accessors(json: &Value, bin: &[u8]) {
+        let buffer_len = json["buffers"][0]["byteLength"].as_u64().unwrap() as usize;
+        assert!(buffer_len <= bin.len());
+        let views = json["bufferViews"].as_array().unwrap();
+        for view in views {
+            let end = view["byteOffset"].as_u64().unwrap() + view["byteLength"].as_u64().unwrap();
+            assert!(end as usize <= buffer_len);
+        }
+        for accessor in json["accessors"].as_array().unwrap() {
+            let view = &views[accessor["bufferView"].as_u64().unwrap() as usize];
+            let components = match accessor["type"].as_str().unwrap() {
+                "SCALAR" => 1,
+                "VEC3" => 3,
+                t => panic!("Unexpected accessor type {}", t),
+            };
+            assert!([FLOAT as u64, UNSIGNED_INT as u64]
+                .contains(&accessor["componentType"].as_u64().unwrap()));
+            let len = accessor["count"].as_u64().unwrap() * components * 4;
+            assert_eq!(view["byteLength"].as_u64().unwrap(), len);
+        }
+    }
+
+    #[test]
+    fn test_glb() {
+        let mut mesh = sphere_mesh(0.9);
+        let mut glb = Vec::new();
+        mesh.write_glb(&mut glb).unwrap();
+        let (json, bin) = parse_glb(&glb);
+        check_accessors(&json, bin);
+
+        assert_eq!("2.0", json["asset"]["version"]);
+        let primitive = &json["meshes"][0]["primitives"][0];
+        assert_eq!(4, primitive["mode"]);
+        assert!(primitive["attributes"]["NORMAL"].is_null());
+
+        let positions =
+            &json["accessors"][primitive["attributes"]["POSITION"].as_u64().unwrap() as usize];
+        assert_eq!(mesh.0.len() as u64, positions["count"]);
+        for axis in 0..3 {
+            let coords = mesh.0.iter().map(|v| [v.0, v.1, v.2][axis]);
+            let min = coords.clone().fold(f32::INFINITY, f32::min);
+            let max = coords.fold(f32::NEG_INFINITY, f32::max);
+            assert_eq!(min, positions["min"][axis].as_f64().unwrap() as f32);
+            assert_eq!(max, positions["max"][axis].as_f64().unwrap() as f32);
+        }
+        assert_eq!(mesh.0[1].2, f32_at(bin, 12 + 8));
+
+        let indices = &json["accessors"][primitive["indices"].as_u64().unwrap() as usize];
+        assert_eq!(3 * mesh.1.len() as u64, indices["count"]);
+        let view = &json["bufferViews"][indices["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        assert_eq!(mesh.1[0].2 as u32, u32_at(bin, offset + 8));
+
+        mesh.compute_vertex_normals();
+        let mut glb = Vec::new();
+        mesh.write_glb(&mut glb).unwrap();
+        let (json, bin) = parse_glb(&glb);
+        check_accessors(&json, bin);
+        let primitive = &json["meshes"][0]["primitives"][0];
+        let normals =
+            &json["accessors"][primitive["attributes"]["NORMAL"].as_u64().unwrap() as usize];
+        assert_eq!(mesh.0.len() as u64, normals["count"]);
+        let view = &json["bufferViews"][normals["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        assert_eq!(mesh.2[0].0, f32_at(bin, offset));
+    }
+
+    #[test]
+    fn test_empty() {
+        let mesh: Mesh = Mesh::empty();
+        assert!(mesh.write_glb(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_index_too_large() {
+        let mesh = Mesh(
+            vec![Vertex(0.0, 0.0, 0.0); 3],
+            vec![Triangle(0, 1, usize::MAX)],
+            Vec::new(),
+        );
+        let mut glb = Vec::new();
+        let result = mesh.write_glb(&mut glb);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        assert!(glb.is_empty());
+    }
+}
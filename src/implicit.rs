@@ -0,0 +1,186 @@
+
+use crate::error::Error;
+use crate::field::grid_position;
+use crate::field::validate_bounds;
+use crate::field::ScalarField;
+use crate::mesh::Mesh;
+use crate::real::Real;
+use crate::tessellator::MeshBuilder;
+use crate::tessellator::MeshOptions;
+
+// Creates mesh of an implicit function without materializing the whole grid. The function is
+// sampled at the same positions as in `Field::from_closure`, and the resulting mesh is the same as
+// with `create_mesh` for such a field. Only the corner layers needed by the current slab of cubes
+// are kept in memory. Fails like `Field::try_from_fn` if the closure returns NaN, if any
+// component of `cube_count` is zero, or if the bounds aren't increasing along every axis.
+pub fn create_mesh_from_closure<T, F>(
+    field: F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    cube_count: &(usize, usize, usize),
+    options: &MeshOptions<T>,
+) -> Result<Mesh<T>, Error>
+where
+    T: Real,
+    F: Fn(T, T, T) -> T,
+{
+    if cube_count.0 == 0 || cube_count.1 == 0 || cube_count.2 == 0 {
+        return Err(Error::TooFewCorners {
+            corner_count: (cube_count.0 + 1, cube_count.1 + 1, cube_count.2 + 1),
+        });
+    }
+    validate_bounds(min_bound, max_bound)?;
+
+    let mut layers = LazyLayers {
+        field,
+        min_bound: *min_bound,
+        max_bound: *max_bound,
+        cube_count: *cube_count,
+        first: 0,
+        layers: Vec::new(),
+    };
+    // Gradient at a corner uses the neighbouring layers as well
+    let margin = if options.normals { 1 } else { 0 };
+
+    let mut builder = MeshBuilder::new(options, min_bound, max_bound, cube_count);
+    for z in 0..cube_count.2 {
+        layers.advance(z.saturating_sub(margin), (z + 1 + margin).min(cube_count.2))?;
+        builder.march_slab(&layers, z);
+    }
+    Ok(builder.finish())
+}
+
+// Window of consecutive corner layers of a closure, sampled on demand
+struct LazyLayers<T, F> {
+    field: F,
+    min_bound: (T, T, T),
+    max_bound: (T, T, T),
+    cube_count: (usize, usize, usize),
+    // Index of the first layer in `layers`
+    first: usize,
+    layers: Vec<Vec<T>>,
+}
+
+impl<T, F> LazyLayers<T, F>
+where
+    T: Real,
+    F: Fn(T, T, T) -> T,
+{
+    // Moves the window to cover layers `first..=last`. Window can only move forward. Fails if a new
+    // layer has NaN samples.
+    fn advance(&mut self, first: usize, last: usize) -> Result<(), Error> {
+        let drop = (first - self.first).min(self.layers.len());
+        let mut recycled: Vec<_> = self.layers.drain(..drop).collect();
+        self.first = first;
+        while self.first + self.layers.len() <= last {
+            let z = self.first + self.layers.len();
+            let mut layer = recycled.pop().unwrap_or_default();
+            self.sample_layer(z, &mut layer)?;
+            self.layers.push(layer);
+        }
+        Ok(())
+    }
+
+    // Samples corner layer `z`, with NaN samples reported at their index in the flat data of the
+    // whole grid
+    fn sample_layer(&self, z: usize, layer: &mut Vec<T>) -> Result<(), Error> {
+        let (min_bound, max_bound) = (&self.min_bound, &self.max_bound);
+        let cube_count = &self.cube_count;
+        layer.clear();
+        for y in 0..(cube_count.1 + 1) {
+            for x in 0..(cube_count.0 + 1) {
+                let fp = grid_position(min_bound, max_bound, cube_count, x, y, z);
+                layer.push((self.field)(fp.0, fp.1, fp.2));
+            }
+        }
+        match layer.iter().position(|v| v.is_nan()) {
+            Some(i) => Err(Error::NotANumber {
+                index: z * layer.len() + i,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Real, F> ScalarField<T> for LazyLayers<T, F> {
+    fn corner_count(&self) -> (usize, usize, usize) {
+        (self.cube_count.0 + 1, self.cube_count.1 + 1, self.cube_count.2 + 1)
+    }
+    fn f(&self, x: usize, y: usize, z: usize) -> T {
+        self.layers[z - self.first][x + (self.cube_count.0 + 1) * y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Field;
+    use crate::tessellator::create_mesh_with;
+
+    #[test]
+    fn test_same_as_field() {
+        let sphere = |x: f32, y: f32, z: f32| (x * x + y * y + z * z).sqrt() - 0.9;
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.2, 1.0));
+        let cube_count = (20, 25, 30);
+
+        for &normals in &[false, true] {
+            let options = MeshOptions {
+                iso_value: 0.1,
+                normals,
+                ..MeshOptions::default()
+            };
+            let field = Field::from_closure(sphere, &min, &max, &cube_count);
+            let expected = create_mesh_with(&field, &min, &max, &options).unwrap();
+            let mesh = create_mesh_from_closure(sphere, &min, &max, &cube_count, &options).unwrap();
+            assert!(!mesh.1.is_empty());
+            assert_eq!(expected.0, mesh.0);
+            assert_eq!(expected.1, mesh.1);
+            assert_eq!(expected.2, mesh.2);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let sphere = |x: f32, y: f32, z: f32| (x * x + y * y + z * z).sqrt() - 0.9;
+        let options = MeshOptions::default();
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let result = create_mesh_from_closure(sphere, &min, &max, &(2, 0, 2), &options);
+        assert_eq!(
+            Some(Error::TooFewCorners {
+                corner_count: (3, 1, 3)
+            }),
+            result.err()
+        );
+        let result = create_mesh_from_closure(sphere, &max, &min, &(2, 2, 2), &options);
+        assert_eq!(Some(Error::InvalidBounds), result.err());
+
+        // NaN samples fail at the same index as with `Field::try_from_fn`
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let cube_count = (3, 4, 5);
+        let f = |x: f32, y: f32, z: f32| (0.5 - x * y * z).sqrt();
+        let expected = Field::try_from_fn(f, &min, &max, &cube_count).err();
+        assert!(matches!(expected, Some(Error::NotANumber { index }) if index > 20));
+        let result = create_mesh_from_closure(f, &min, &max, &cube_count, &options);
+        assert_eq!(expected, result.err());
+    }
+
+    #[test]
+    fn test_window() {
+        let mut layers = LazyLayers {
+            field: |_x: f32, _y: f32, z: f32| z,
+            min_bound: (0.0, 0.0, 0.0),
+            max_bound: (1.0, 1.0, 4.0),
+            cube_count: (1, 1, 4),
+            first: 0,
+            layers: Vec::new(),
+        };
+        layers.advance(0, 1).unwrap();
+        assert_eq!(2, layers.layers.len());
+        assert_eq!(1.0, layers.f(1, 1, 1));
+        layers.advance(2, 4).unwrap();
+        assert_eq!(3, layers.layers.len());
+        assert_eq!(2.0, layers.f(0, 0, 2));
+        assert_eq!(4.0, layers.f(1, 0, 4));
+    }
+}
@@ -1,11 +1,52 @@
-#![feature(nll)]
-
+mod error;
+mod real;
 mod tables;
+mod contour;
 mod cube;
+mod mc33;
+mod tetrahedra;
 mod field;
 mod mesh;
 mod tessellator;
+mod surface_nets;
+mod dual_contouring;
+mod implicit;
+mod obj;
+mod stl;
+mod ply;
+mod gltf;
+mod raw;
+mod nrrd;
+mod metaimage;
+mod vtk;
+#[cfg(feature = "parallel")]
+mod parallel;
 
+pub use error::Error;
+pub use field::Bounds;
 pub use field::Field;
+pub use field::ScalarField;
+pub use raw::Endianness;
+pub use raw::SampleType;
+pub use real::Real;
 pub use tessellator::create_mesh;
-pub use mesh::Mesh;
\ No newline at end of file
+pub use tessellator::create_mesh_iso;
+pub use tessellator::create_mesh_with;
+pub use tessellator::MeshOptions;
+pub use tessellator::Tessellation;
+pub use surface_nets::create_surface_net;
+pub use surface_nets::Quad;
+pub use surface_nets::QuadMesh;
+pub use dual_contouring::create_dual_contour;
+pub use dual_contouring::create_dual_contour_with_gradient;
+pub use implicit::create_mesh_from_closure;
+#[cfg(feature = "parallel")]
+pub use parallel::create_mesh_parallel;
+pub use mesh::Mesh;
+pub use mesh::Vertex;
+pub use mesh::Normal;
+pub use mesh::Triangle;
+pub use stl::StlReport;
+pub use ply::PlyFormat;
+pub use ply::VertexAttributes;
+pub use vtk::VtkFormat;
\ No newline at end of file
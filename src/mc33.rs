@@ -0,0 +1,343 @@
+
+use crate::contour::tessellate_pieces;
+use crate::contour::Loops;
+use crate::cube::edge_point;
+use crate::real::Real;
+use crate::tables::EDGES;
+
+// Tessellates a cube like `tessellate_corners_into`, but with the topology of the trilinear
+// interpolant of the corner values as in Chernyaev's Marching Cubes 33. Ambiguous faces are
+// resolved with the asymptotic decider, so adjacent cubes always agree on the contour along their
+// shared face, and the interior test decides whether separate contours on the cube surface are
+// joined by a tunnel through the cube.
+//
+// Instead of MC33's case tables, contours are traced around the cube surface as loops of edge
+// intersections, and each piece of surface between a connected inside and outside region is
+// triangulated from its loops. No triangle or diagonal is placed on a face of the cube, where it
+// could overlap with the neighbouring cube. Vertices inside the cube are written to `verts` from
+// `INNER_VERTEX` onwards. Has the same requirements for `verts` and `tris` as
+// `tessellate_corners_into`.
+pub fn tessellate_corners_mc33_into<T: Real>(
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+    verts: &mut [(T, T, T)],
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let mut g = [T::zero(); 8];
+    for (g, &f) in g.iter_mut().zip(f) {
+        *g = f - iso;
+    }
+    let loops = Loops::trace(&g);
+    if loops.count == 0 {
+        return 0;
+    }
+    for &e in &loops.edges[..loops.ends[loops.count - 1]] {
+        verts[e] = edge_point(e, p, f, iso);
+    }
+
+    // Each piece of surface separates one connected inside region from one connected outside
+    // region, so loops bordering the same pair of regions belong to the same piece
+    let inside = regions(&g, |v| v < T::zero(), |a, b| a > b);
+    let outside = regions(&g, |v| v >= T::zero(), |a, b| a >= b);
+    let mut keys = [(0, 0); 4];
+    for (l, key) in keys.iter_mut().enumerate().take(loops.count) {
+        let (c0, c1) = EDGES[loops.edges[loops.start(l)]];
+        let (c_in, c_out) = if g[c0] < T::zero() {
+            (c0, c1)
+        } else {
+            (c1, c0)
+        };
+        *key = (inside[c_in], outside[c_out]);
+    }
+
+    tessellate_pieces(verts, &loops, &keys[..loops.count], tris)
+}
+
+// Connected components of the region where `in_region` holds for the trilinear interpolant,
+// labeled at each corner. Corners outside the region have meaningless labels.
+//
+// Every component of the region in a cross-section of the cube perpendicular to the y axis reaches
+// a corner of the cross-section, so components can be found by joining the y-directed edges of
+// the cube. Diagonal corners of a cross-section are joined when the saddle of the bilinear
+// cross-section is in the region, as given by `joined_diagonal` for the products of the values
+// at the region's diagonal and the other diagonal. This is the interior test of MC33.
+fn regions<T: Real, I, J>(g: &[T; 8], in_region: I, joined_diagonal: J) -> [usize; 8]
+where
+    I: Fn(T) -> bool,
+    J: Fn(T, T) -> bool,
+{
+    // Values at the corners of a cross-section, in the cyclic order of corners 0, 1, 2 and 3
+    let joined = |h: [T; 4], k: usize, j: usize, m: usize, n: usize| {
+        in_region(h[k])
+            && in_region(h[j])
+            && !in_region(h[m])
+            && !in_region(h[n])
+            && joined_diagonal(h[k] * h[j], h[m] * h[n])
+    };
+    let bottom = [g[0], g[1], g[2], g[3]];
+    let top = [g[4], g[5], g[6], g[7]];
+
+    let mut parent = [0, 1, 2, 3];
+    fn find(parent: &[usize; 4], mut i: usize) -> usize {
+        while parent[i] != i {
+            i = parent[i];
+        }
+        i
+    }
+    let mut union = |a: usize, b: usize| {
+        let (a, b) = (find(&parent, a), find(&parent, b));
+        parent[a.max(b)] = a.min(b);
+    };
+
+    // Edges on the same side face are joined at the bottom or top face, or through the side face
+    for k in 0..4 {
+        let j = (k + 1) % 4;
+        let side = [bottom[k], bottom[j], top[j], top[k]];
+        if (in_region(bottom[k]) && in_region(bottom[j]))
+            || (in_region(top[k]) && in_region(top[j]))
+            || joined(side, 0, 2, 1, 3)
+            || joined(side, 1, 3, 0, 2)
+        {
+            union(k, j);
+        }
+    }
+
+    // Diagonal edges are joined at the bottom or top face, or through a cross-section inside the
+    // cube. Only the cross-section where the products of the diagonals differ the most needs to be
+    // tested, as elsewhere one of the other edges is in the region and already joined to both.
+    for &(k, j, m, n) in &[(0, 2, 1, 3), (1, 3, 0, 2)] {
+        let mut is_joined = joined(bottom, k, j, m, n) || joined(top, k, j, m, n);
+        let d = [
+            top[0] - bottom[0],
+            top[1] - bottom[1],
+            top[2] - bottom[2],
+            top[3] - bottom[3],
+        ];
+        let a = d[k] * d[j] - d[m] * d[n];
+        let b = bottom[k] * d[j] + bottom[j] * d[k] - bottom[m] * d[n] - bottom[n] * d[m];
+        if a != T::zero() {
+            let t = -b / (a + a);
+            if t > T::zero() && t < T::one() {
+                let mut h = bottom;
+                for (h, &d) in h.iter_mut().zip(&d) {
+                    *h += d * t;
+                }
+                is_joined = is_joined || joined(h, k, j, m, n);
+            }
+        }
+        if is_joined {
+            union(k, j);
+        }
+    }
+
+    let mut labels = [0; 8];
+    for (c, label) in labels.iter_mut().enumerate() {
+        *label = find(&parent, c % 4);
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+
+    use crate::contour::INNER_VERTEX;
+    use crate::cube::has_ambiguous_face;
+    use crate::cube::tessellate_corners_into;
+    use crate::cube::CUBE_TRI_COUNT;
+    use crate::cube::CUBE_VERT_COUNT;
+    use crate::mesh::Mesh;
+    use crate::mesh::Triangle;
+    use crate::mesh::Vertex;
+    use crate::tables::CORNERS;
+
+    fn unit_cube() -> Vec<(f64, f64, f64)> {
+        CORNERS
+            .iter()
+            .map(|&(x, y, z)| (x as f64, y as f64, z as f64))
+            .collect()
+    }
+
+    fn tessellate(f: &[f64]) -> Mesh<f64> {
+        let mut verts = [(0.0, 0.0, 0.0); CUBE_VERT_COUNT];
+        let mut tris = [(0, 0, 0); CUBE_TRI_COUNT];
+        let tri_count = tessellate_corners_mc33_into(&unit_cube(), f, 0.0, &mut verts, &mut tris);
+        Mesh::new(
+            verts.iter().map(|&(x, y, z)| Vertex(x, y, z)).collect(),
+            tris[..tri_count]
+                .iter()
+                .map(|&(i0, i1, i2)| Triangle(i0, i1, i2))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_unambiguous_cases() {
+        // Without ambiguous faces or interior, MC33 gives the same surface as the classic table
+        let mut verts = [(0.0, 0.0, 0.0); CUBE_VERT_COUNT];
+        let mut tris = [(0, 0, 0); CUBE_TRI_COUNT];
+        for case in 0..256 {
+            let f: Vec<f64> = (0..8)
+                .map(|i| if case & (1 << i) != 0 { -1.0 } else { 1.0 })
+                .collect();
+            let mc33 = tessellate(&f);
+            let ambiguous = has_ambiguous_face(&f, 0.0);
+            let tri_count = tessellate_corners_into(&unit_cube(), &f, 0.0, &mut verts, &mut tris);
+            if !ambiguous {
+                assert_eq!(tri_count, mc33.1.len(), "case {}", case);
+            }
+            // Every edge with a sign change is used
+            for (e, &(c0, c1)) in EDGES.iter().enumerate() {
+                let crossed = (f[c0] < 0.0) != (f[c1] < 0.0);
+                let used = mc33.1.iter().any(|t| t.0 == e || t.1 == e || t.2 == e);
+                assert_eq!(crossed, used, "case {} edge {}", case, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_orientation() {
+        // Corner 0 inside, so the triangle faces towards (1, 1, 1)
+        let m = tessellate(&[-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(1, m.1.len());
+        let n = &m.face_normals()[0];
+        assert!(n.0 > 0.0 && (n.0 - n.1).abs() < 1e-9 && (n.0 - n.2).abs() < 1e-9);
+
+        // All corners but 0 inside, so the triangle faces towards corner 0
+        let m = tessellate(&[1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0]);
+        let n = &m.face_normals()[0];
+        assert!(n.0 < 0.0 && (n.0 - n.1).abs() < 1e-9 && (n.0 - n.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_face_ambiguity() {
+        // Corners 0 and 2 on the y = 0 face are inside, joined across the face when their values
+        // dominate the saddle of the face
+        let separate = tessellate(&[-1.0, 1.0, -1.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+        assert_eq!(2, separate.1.len());
+        assert_eq!(2, separate.components().len());
+        let joined = tessellate(&[-2.0, 0.5, -2.0, 0.5, 2.0, 2.0, 2.0, 2.0]);
+        assert_eq!(4, joined.1.len());
+        assert_eq!(1, joined.components().len());
+    }
+
+    #[test]
+    fn test_tunnel() {
+        // Opposite corners 0 and 6 inside: two separate corners, or a tunnel through the cube
+        // when they are deep enough
+        let corners = tessellate(&[-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, -1.0, 1.0]);
+        assert_eq!(2, corners.1.len());
+        assert_eq!(2, corners.components().len());
+        let tunnel = tessellate(&[-1.0, 0.1, 0.1, 0.1, 0.1, 0.1, -1.0, 0.1]);
+        assert_eq!(6, tunnel.1.len());
+        assert_eq!(1, tunnel.components().len());
+
+        // Same with the inside and outside swapped, where the tunnel is outside
+        let corners = tessellate(&[1.0, -1.0, -1.0, -1.0, -1.0, -1.0, 1.0, -1.0]);
+        assert_eq!(2, corners.1.len());
+        let tunnel = tessellate(&[1.0, -0.1, -0.1, -0.1, -0.1, -0.1, 1.0, -0.1]);
+        assert_eq!(6, tunnel.1.len());
+    }
+
+    #[test]
+    fn test_center_vertex() {
+        // Corners 0, 2, 5 and 7 inside, with faces resolved so that a single loop goes around the
+        // cube through all 12 edges
+        let m = tessellate(&[-0.3, 0.5, -0.65, 0.15, 0.65, -1.0, 0.7, -0.2]);
+        assert_eq!(12, m.1.len());
+        assert!(m.1.iter().all(|t| t.0 == INNER_VERTEX));
+    }
+
+    // Whether the triangles around vertex `v` form a single fan, a cycle for vertices inside the
+    // cube and a path for vertices on its edges, with consistent orientation
+    fn is_single_fan(tris: &[Triangle], v: usize) -> bool {
+        let mut next = HashMap::new();
+        for t in tris {
+            let (p, q) = match (t.0 == v, t.1 == v, t.2 == v) {
+                (true, _, _) => (t.1, t.2),
+                (_, true, _) => (t.2, t.0),
+                (_, _, true) => (t.0, t.1),
+                _ => continue,
+            };
+            if next.insert(p, q).is_some() {
+                return false;
+            }
+        }
+        let starts: Vec<usize> = next
+            .keys()
+            .cloned()
+            .filter(|p| !next.values().any(|q| q == p))
+            .collect();
+        let start = match (starts.len(), v >= INNER_VERTEX) {
+            (0, _) => match next.keys().next() {
+                Some(&p) => p,
+                None => return true,
+            },
+            (1, false) => starts[0],
+            _ => return false,
+        };
+        let mut count = 0;
+        let mut p = start;
+        while let Some(&q) = next.get(&p) {
+            count += 1;
+            p = q;
+            if p == start {
+                break;
+            }
+        }
+        count == next.len()
+    }
+
+    #[test]
+    fn test_manifold_fans() {
+        // Two loops that can't be joined directly, joined by a tube through the cube. Values are in
+        // the x-major order of `Field::from_flat`.
+        let flat = [
+            0.926954, -0.563752, -0.076042, 0.038758, -0.937292, -0.839864, -0.333008, 0.721116,
+        ];
+        let f: Vec<f64> = CORNERS
+            .iter()
+            .map(|&(x, y, z)| flat[x + 2 * y + 4 * z])
+            .collect();
+        let tube = tessellate(&f);
+        let used: HashSet<usize> = tube.1.iter().flat_map(|t| vec![t.0, t.1, t.2]).collect();
+        let edges: HashSet<(usize, usize)> = tube
+            .1
+            .iter()
+            .flat_map(|t| vec![(t.0, t.1), (t.1, t.2), (t.2, t.0)])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        assert_eq!(0, used.len() + tube.1.len() - edges.len());
+        for &v in used.iter().filter(|&&v| v >= INNER_VERTEX) {
+            let p = &tube.0[v];
+            assert!([p.0, p.1, p.2].iter().all(|&x| x > 0.0 && x < 1.0));
+        }
+
+        // Random cubes, and all cubes with a few values so that faces and interiors are also
+        // resolved with ties
+        let mut seed = 7u64;
+        let mut random = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64 * 2.0 - 1.0
+        };
+        let random_cubes = (0..20000).map(|_| (0..8).map(|_| random()).collect::<Vec<_>>());
+        let levels = [-2.0, -1.0, 1.0, 2.0];
+        let level_cubes = (0..1 << 16).map(|case: usize| {
+            (0..8)
+                .map(|i| levels[(case >> (2 * i)) & 3])
+                .collect::<Vec<_>>()
+        });
+        for f in random_cubes.chain(level_cubes) {
+            let m = tessellate(&f);
+            for v in 0..16 {
+                assert!(is_single_fan(&m.1, v), "{:?} vertex {}", f, v);
+            }
+        }
+    }
+}
@@ -1,16 +1,396 @@
+use std::collections::HashMap;
 
+use crate::real::Real;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Vertex<T = f32>(pub T, pub T, pub T);
 #[derive(Clone, PartialEq, Debug)]
-pub struct Vertex(pub f32, pub f32, pub f32);
+pub struct Normal<T = f32>(pub T, pub T, pub T);
 #[derive(Clone, PartialEq, Debug)]
 pub struct Triangle(pub usize, pub usize, pub usize);
 
-pub struct Mesh(pub Vec<Vertex>, pub Vec<Triangle>);
+// Vertices, triangles and per-vertex normals. Normals are either empty, or there is exactly one
+// normal for each vertex.
+pub struct Mesh<T = f32>(pub Vec<Vertex<T>>, pub Vec<Triangle>, pub Vec<Normal<T>>);
+
+impl<T: Real> Mesh<T> {
+    pub fn new(verts: Vec<Vertex<T>>, tris: Vec<Triangle>) -> Mesh<T> {
+        Mesh(verts, tris, Vec::new())
+    }
+    pub fn with_normals(
+        verts: Vec<Vertex<T>>,
+        tris: Vec<Triangle>,
+        normals: Vec<Normal<T>>,
+    ) -> Mesh<T> {
+        assert_eq!(verts.len(), normals.len());
+        Mesh(verts, tris, normals)
+    }
+    pub fn empty() -> Mesh<T> {
+        Mesh(Vec::new(), Vec::new(), Vec::new())
+    }
+    pub fn has_normals(&self) -> bool {
+        !self.2.is_empty()
+    }
+}
+
+impl<T: Real> Mesh<T> {
+    // Computes per-vertex normals as the sum of the normals of adjacent triangles, weighted by
+    // triangle area.
+    pub fn compute_vertex_normals(&mut self) {
+        let mut normals = vec![(T::zero(), T::zero(), T::zero()); self.0.len()];
+        for t in &self.1 {
+            // Length of the cross product is twice the triangle's area
+            let n = cross(&self.0[t.0], &self.0[t.1], &self.0[t.2]);
+            for &i in &[t.0, t.1, t.2] {
+                add(&mut normals[i], &n, T::one());
+            }
+        }
+        self.2 = normals.iter().map(normalize).collect();
+    }
+
+    // Computes per-vertex normals as the sum of the normals of adjacent triangles, weighted by
+    // the angle of each triangle at the vertex. Unlike area weighting, this doesn't depend on how
+    // a flat region happens to be split into triangles.
+    pub fn compute_vertex_normals_angle_weighted(&mut self) {
+        let mut normals = vec![(T::zero(), T::zero(), T::zero()); self.0.len()];
+        for t in &self.1 {
+            let n = normalize(&cross(&self.0[t.0], &self.0[t.1], &self.0[t.2]));
+            let n = (n.0, n.1, n.2);
+            for &(i0, i1, i2) in &[(t.0, t.1, t.2), (t.1, t.2, t.0), (t.2, t.0, t.1)] {
+                let a = angle(&self.0[i0], &self.0[i1], &self.0[i2]);
+                add(&mut normals[i0], &n, a);
+            }
+        }
+        self.2 = normals.iter().map(normalize).collect();
+    }
+
+    // Splits vertices along creases, i.e. edges where adjacent triangles meet at an angle greater
+    // than `crease_angle` (in radians). Each smooth group of triangles around a vertex gets its own
+    // copy of the vertex, and area-weighted normals are computed for the result.
+    pub fn split_creases(&mut self, crease_angle: T) {
+        let tris = self.1.clone();
+        let face_normals: Vec<_> = tris
+            .iter()
+            .map(|t| cross(&self.0[t.0], &self.0[t.1], &self.0[t.2]))
+            .collect();
+        let mut vert_faces = vec![Vec::new(); self.0.len()];
+        for (i, t) in tris.iter().enumerate() {
+            vert_faces[t.0].push(i);
+            vert_faces[t.1].push(i);
+            vert_faces[t.2].push(i);
+        }
+        let cos_crease = crease_angle.cos();
+
+        let mut normals = vec![(T::zero(), T::zero(), T::zero()); self.0.len()];
+        for (v, faces) in vert_faces.iter().enumerate() {
+            // Group triangles around the vertex: triangles sharing an edge end up in the same group
+            // if the angle between them is below the crease angle.
+            let mut group: Vec<usize> = (0..faces.len()).collect();
+            for a in 0..faces.len() {
+                for b in (a + 1)..faces.len() {
+                    let (fa, fb) = (faces[a], faces[b]);
+                    if group[a] == group[b] || !shares_edge(&tris[fa], &tris[fb], v) {
+                        continue;
+                    }
+                    let (na, nb) = (&face_normals[fa], &face_normals[fb]);
+                    let len = (dot(na, na) * dot(nb, nb)).sqrt();
+                    if len > T::zero() && dot(na, nb) / len < cos_crease {
+                        continue;
+                    }
+                    let (old, new) = (group[b], group[a]);
+                    for g in group.iter_mut() {
+                        if *g == old {
+                            *g = new;
+                        }
+                    }
+                }
+            }
+
+            // First group keeps the original vertex, others get a copy
+            let mut group_verts: Vec<(usize, usize)> = Vec::new();
+            for (&f, &g) in faces.iter().zip(&group) {
+                let nv = if let Some(&(_, nv)) = group_verts.iter().find(|&&(gg, _)| gg == g) {
+                    nv
+                } else {
+                    let nv = if group_verts.is_empty() {
+                        v
+                    } else {
+                        self.0.push(self.0[v].clone());
+                        normals.push((T::zero(), T::zero(), T::zero()));
+                        self.0.len() - 1
+                    };
+                    group_verts.push((g, nv));
+                    nv
+                };
+                add(&mut normals[nv], &face_normals[f], T::one());
+                let t = &mut self.1[f];
+                if t.0 == v {
+                    t.0 = nv;
+                } else if t.1 == v {
+                    t.1 = nv;
+                } else {
+                    t.2 = nv;
+                }
+            }
+        }
+        self.2 = normals.iter().map(normalize).collect();
+    }
+
+    // Unit normal of each triangle. Triangles are wound counter-clockwise when seen from outside
+    // of the volume, so normals point outwards.
+    pub fn face_normals(&self) -> Vec<Normal<T>> {
+        self.1
+            .iter()
+            .map(|t| normalize(&cross(&self.0[t.0], &self.0[t.1], &self.0[t.2])))
+            .collect()
+    }
+}
 
-impl Mesh {
-    pub fn new(verts: Vec<Vertex>, tris: Vec<Triangle>) -> Mesh {
-        Mesh(verts, tris)
+impl<T> Mesh<T> {
+    // Edges that don't join exactly two consistently wound triangles, as vertex index pairs with
+    // the smaller index first. A mesh is closed when there are no such edges. Surfaces cut by the
+    // boundary of the field are open.
+    pub fn open_edges(&self) -> Vec<(usize, usize)> {
+        // Number of triangles using the edge, and sum of their directions along it
+        let mut edges: HashMap<(usize, usize), (usize, isize)> = HashMap::new();
+        for t in &self.1 {
+            for &(a, b) in &[(t.0, t.1), (t.1, t.2), (t.2, t.0)] {
+                let e = edges.entry((a.min(b), a.max(b))).or_insert((0, 0));
+                e.0 += 1;
+                e.1 += if a < b { 1 } else { -1 };
+            }
+        }
+        let mut open: Vec<_> = edges
+            .into_iter()
+            .filter(|&(_, (count, dir))| count != 2 || dir != 0)
+            .map(|(e, _)| e)
+            .collect();
+        open.sort();
+        open
     }
-    pub fn empty() -> Mesh {
-        Mesh(Vec::new(), Vec::new())
+
+    pub fn is_closed(&self) -> bool {
+        self.open_edges().is_empty()
+    }
+
+    // Triangles grouped to connected components, i.e. sets of triangles connected to each other
+    // through shared vertices. Components are ordered by their first triangle.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut parent: Vec<usize> = (0..self.0.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for t in &self.1 {
+            let r0 = root(&mut parent, t.0);
+            let r1 = root(&mut parent, t.1);
+            parent[r1] = r0;
+            let r2 = root(&mut parent, t.2);
+            parent[r2] = r0;
+        }
+
+        let mut component_of_root = vec![None; self.0.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for (i, t) in self.1.iter().enumerate() {
+            let r = root(&mut parent, t.0);
+            let c = *component_of_root[r].get_or_insert_with(|| {
+                components.push(Vec::new());
+                components.len() - 1
+            });
+            components[c].push(i);
+        }
+        components
+    }
+}
+
+fn shares_edge(t0: &Triangle, t1: &Triangle, v: usize) -> bool {
+    [t0.0, t0.1, t0.2]
+        .iter()
+        .any(|&i| i != v && (i == t1.0 || i == t1.1 || i == t1.2))
+}
+
+fn cross<T: Real>(v0: &Vertex<T>, v1: &Vertex<T>, v2: &Vertex<T>) -> (T, T, T) {
+    let e0 = (v1.0 - v0.0, v1.1 - v0.1, v1.2 - v0.2);
+    let e1 = (v2.0 - v0.0, v2.1 - v0.1, v2.2 - v0.2);
+    (
+        e0.1 * e1.2 - e0.2 * e1.1,
+        e0.2 * e1.0 - e0.0 * e1.2,
+        e0.0 * e1.1 - e0.1 * e1.0,
+    )
+}
+
+fn dot<T: Real>(a: &(T, T, T), b: &(T, T, T)) -> T {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+fn add<T: Real>(a: &mut (T, T, T), b: &(T, T, T), w: T) {
+    a.0 += b.0 * w;
+    a.1 += b.1 * w;
+    a.2 += b.2 * w;
+}
+
+fn normalize<T: Real>(n: &(T, T, T)) -> Normal<T> {
+    let len = dot(n, n).sqrt();
+    if len > T::zero() {
+        Normal(n.0 / len, n.1 / len, n.2 / len)
+    } else {
+        Normal(T::zero(), T::zero(), T::zero())
+    }
+}
+
+// Angle of triangle (v0, v1, v2) at v0
+fn angle<T: Real>(v0: &Vertex<T>, v1: &Vertex<T>, v2: &Vertex<T>) -> T {
+    let e0 = (v1.0 - v0.0, v1.1 - v0.1, v1.2 - v0.2);
+    let e1 = (v2.0 - v0.0, v2.1 - v0.1, v2.2 - v0.2);
+    let len = (dot(&e0, &e0) * dot(&e1, &e1)).sqrt();
+    if len > T::zero() {
+        (dot(&e0, &e1) / len).max(-T::one()).min(T::one()).acos()
+    } else {
+        T::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cube (-1,-1,-1)-(1,1,1) with outwards facing triangles. Vertex i is at corner
+    // (x, y, z) = (i & 1, (i >> 1) & 1, (i >> 2) & 1) scaled to the cube.
+    fn cube() -> Mesh {
+        let verts = (0..8)
+            .map(|i| {
+                Vertex(
+                    ((i & 1) * 2) as f32 - 1.0,
+                    (((i >> 1) & 1) * 2) as f32 - 1.0,
+                    (((i >> 2) & 1) * 2) as f32 - 1.0,
+                )
+            })
+            .collect();
+        let tris = vec![
+            Triangle(0, 2, 1),
+            Triangle(1, 2, 3),
+            Triangle(4, 5, 6),
+            Triangle(5, 7, 6),
+            Triangle(0, 1, 5),
+            Triangle(0, 5, 4),
+            Triangle(2, 6, 7),
+            Triangle(2, 7, 3),
+            Triangle(0, 4, 6),
+            Triangle(0, 6, 2),
+            Triangle(1, 3, 7),
+            Triangle(1, 7, 5),
+        ];
+        Mesh::new(verts, tris)
+    }
+
+    fn octahedron() -> Mesh {
+        let verts = vec![
+            Vertex(1.0, 0.0, 0.0),
+            Vertex(-1.0, 0.0, 0.0),
+            Vertex(0.0, 1.0, 0.0),
+            Vertex(0.0, -1.0, 0.0),
+            Vertex(0.0, 0.0, 1.0),
+            Vertex(0.0, 0.0, -1.0),
+        ];
+        let tris = vec![
+            Triangle(0, 2, 4),
+            Triangle(2, 1, 4),
+            Triangle(1, 3, 4),
+            Triangle(3, 0, 4),
+            Triangle(2, 0, 5),
+            Triangle(1, 2, 5),
+            Triangle(3, 1, 5),
+            Triangle(0, 3, 5),
+        ];
+        Mesh::new(verts, tris)
+    }
+
+    fn assert_normal(n: &Normal, expected: (f32, f32, f32)) {
+        assert!((n.0 - expected.0).abs() < 0.00001, "{:?} != {:?}", n, expected);
+        assert!((n.1 - expected.1).abs() < 0.00001, "{:?} != {:?}", n, expected);
+        assert!((n.2 - expected.2).abs() < 0.00001, "{:?} != {:?}", n, expected);
+    }
+
+    #[test]
+    fn test_area_weighted() {
+        let mut m = octahedron();
+        m.compute_vertex_normals();
+        assert_eq!(m.0.len(), m.2.len());
+        for (v, n) in m.0.iter().zip(&m.2) {
+            assert_normal(n, (v.0, v.1, v.2));
+        }
+    }
+
+    #[test]
+    fn test_angle_weighted() {
+        // Each corner of the cube sees all three sides with the same angle, although the number
+        // of adjacent triangles differs.
+        let mut m = cube();
+        m.compute_vertex_normals_angle_weighted();
+        let d = 1.0 / (3.0f32).sqrt();
+        for (v, n) in m.0.iter().zip(&m.2) {
+            assert_normal(n, (v.0 * d, v.1 * d, v.2 * d));
+        }
+    }
+
+    #[test]
+    fn test_components() {
+        let mut m = cube();
+        let o = octahedron();
+        let offset = m.0.len();
+        m.0.extend(o.0);
+        m.1.insert(0, Triangle(offset, offset + 2, offset + 4));
+        m.1.extend(
+            o.1.iter()
+                .skip(1)
+                .map(|t| Triangle(t.0 + offset, t.1 + offset, t.2 + offset)),
+        );
+
+        let c = m.components();
+        assert_eq!(2, c.len());
+        assert_eq!(0, c[0][0]);
+        assert_eq!(8, c[0].len());
+        assert_eq!((1..13).collect::<Vec<_>>(), c[1]);
+    }
+
+    #[test]
+    fn test_open_edges() {
+        let mut m = cube();
+        assert!(m.is_closed());
+        assert_eq!(Normal(0.0, 0.0, -1.0), m.face_normals()[0]);
+
+        m.1.pop();
+        assert_eq!(vec![(1, 5), (1, 7), (5, 7)], m.open_edges());
+
+        // Flipped triangle
+        let mut m = cube();
+        m.1[0] = Triangle(0, 1, 2);
+        assert_eq!(vec![(0, 1), (0, 2), (1, 2)], m.open_edges());
+    }
+
+    #[test]
+    fn test_split_creases() {
+        // Every corner of the cube is split in three, each with normal of a side
+        let mut m = cube();
+        m.split_creases(0.5);
+        assert_eq!(24, m.0.len());
+        assert_eq!(24, m.2.len());
+        assert_eq!(12, m.1.len());
+        for t in &m.1 {
+            let n = cross(&m.0[t.0], &m.0[t.1], &m.0[t.2]);
+            let n = normalize(&n);
+            for &i in &[t.0, t.1, t.2] {
+                assert_eq!(n, m.2[i]);
+            }
+        }
+
+        // Crease angle above 90 degrees keeps the cube intact
+        let mut m = cube();
+        m.split_creases(2.0);
+        assert_eq!(8, m.0.len());
+        assert_eq!(8, m.2.len());
     }
 }
@@ -0,0 +1,352 @@
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+
+use crate::error::invalid_data;
+use crate::field::axes_transform;
+use crate::field::Bounds;
+use crate::field::Field;
+use crate::raw::read_samples;
+use crate::raw::Endianness;
+use crate::raw::SampleType;
+use crate::real::Real;
+
+impl<T: Real> Field<T> {
+    // Reads a three dimensional volume from a MetaImage file, either .mha with local data or .mhd
+    // with the data in a separate file. Data can be uncompressed or zlib-compressed. The field is
+    // placed in world space by `Offset`, `ElementSpacing` and `TransformMatrix` in the header, and
+    // the returned bounds enclose its grid.
+    pub fn read_metaimage<P: AsRef<Path>>(path: P) -> io::Result<(Field<T>, Bounds<T>)> {
+        let path = path.as_ref();
+        let mut r = BufReader::new(File::open(path)?);
+        let header: MetaImageHeader<T> = MetaImageHeader::read(&mut r)?;
+        let data: Box<dyn Read> = match header.data_file {
+            // Data file is relative to the header
+            Some(ref file) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                Box::new(BufReader::new(File::open(dir.join(file))?))
+            }
+            None => Box::new(r),
+        };
+        let data: Box<dyn Read> = if header.compressed {
+            Box::new(ZlibDecoder::new(data))
+        } else {
+            data
+        };
+        let samples = read_samples(data, header.dims, header.sample_type, header.endianness)?;
+        let field = Field::try_from_flat(header.dims, samples)?.with_spacing(header.spacing);
+        let field = match header.axes {
+            Some(ref axes) => field.with_transform(axes_transform(axes, header.offset)),
+            None => field.with_origin(header.offset),
+        };
+        let bounds = field.bounds().unwrap();
+        Ok((field, bounds))
+    }
+}
+
+struct MetaImageHeader<T> {
+    sample_type: SampleType,
+    dims: (usize, usize, usize),
+    endianness: Endianness,
+    compressed: bool,
+    offset: (T, T, T),
+    spacing: (T, T, T),
+    // Directions of the grid axes, if they aren't aligned with world axes
+    axes: Option<[(T, T, T); 3]>,
+    // `None` when data follows the header in the same file
+    data_file: Option<String>,
+}
+
+impl<T: Real> MetaImageHeader<T> {
+    // Reads header fields up to and including `ElementDataFile`, which is always the last one
+    fn read<R: BufRead>(r: &mut R) -> io::Result<MetaImageHeader<T>> {
+        let mut sample_type = None;
+        let mut dims = None;
+        let mut data_file = None;
+        let mut header = MetaImageHeader {
+            sample_type: SampleType::U8,
+            dims: (0, 0, 0),
+            endianness: Endianness::Little,
+            compressed: false,
+            offset: (T::zero(), T::zero(), T::zero()),
+            spacing: (T::one(), T::one(), T::one()),
+            axes: None,
+            data_file: None,
+        };
+        let mut line = String::new();
+        while data_file.is_none() {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Err(invalid_data("Missing MetaImage ElementDataFile"));
+            }
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[(i + 1)..].trim()),
+                None => return Err(invalid_data("Invalid MetaImage header line")),
+            };
+            match key {
+                "ObjectType" if value != "Image" => {
+                    return Err(invalid_data("MetaImage object is not an image"))
+                }
+                "NDims" if value != "3" => {
+                    return Err(invalid_data(
+                        "Only three dimensional MetaImage is supported",
+                    ))
+                }
+                "DimSize" => dims = Some(parse_dims(value)?),
+                "ElementType" => sample_type = Some(parse_type(value)?),
+                "ElementSpacing" => header.spacing = parse_triplet(value)?,
+                "Offset" | "Origin" | "Position" => header.offset = parse_triplet(value)?,
+                "BinaryDataByteOrderMSB" | "ElementByteOrderMSB" => {
+                    header.endianness = if parse_bool(value)? {
+                        Endianness::Big
+                    } else {
+                        Endianness::Little
+                    }
+                }
+                "CompressedData" => header.compressed = parse_bool(value)?,
+                "BinaryData" if !parse_bool(value)? => {
+                    return Err(invalid_data("ASCII MetaImage data is not supported"))
+                }
+                "ElementNumberOfChannels" if value != "1" => {
+                    return Err(invalid_data("Multi-channel MetaImage is not supported"))
+                }
+                "TransformMatrix" | "Rotation" | "Orientation" => header.axes = parse_axes(value)?,
+                "HeaderSize" if value != "0" => {
+                    return Err(invalid_data("MetaImage HeaderSize is not supported"))
+                }
+                "ElementDataFile" => data_file = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        header.data_file = match data_file {
+            Some(ref f) if f == "LOCAL" => None,
+            Some(ref f) if f.starts_with("LIST") || f.contains('%') => {
+                return Err(invalid_data(
+                    "Multiple MetaImage data files are not supported",
+                ));
+            }
+            f => f,
+        };
+        header.sample_type =
+            sample_type.ok_or_else(|| invalid_data("Missing MetaImage ElementType"))?;
+        header.dims = dims.ok_or_else(|| invalid_data("Missing MetaImage DimSize"))?;
+        Ok(header)
+    }
+}
+
+fn parse_type(value: &str) -> io::Result<SampleType> {
+    Ok(match value {
+        "MET_UCHAR" => SampleType::U8,
+        "MET_USHORT" => SampleType::U16,
+        "MET_SHORT" => SampleType::I16,
+        "MET_FLOAT" => SampleType::F32,
+        "MET_DOUBLE" => SampleType::F64,
+        _ => return Err(invalid_data("Unsupported MetaImage ElementType")),
+    })
+}
+
+fn parse_dims(value: &str) -> io::Result<(usize, usize, usize)> {
+    let dims = value
+        .split_whitespace()
+        .map(|s| {
+            s.parse()
+                .map_err(|_| invalid_data("Invalid MetaImage DimSize"))
+        })
+        .collect::<io::Result<Vec<usize>>>()?;
+    match dims.as_slice() {
+        &[x, y, z] if x > 1 && y > 1 && z > 1 => Ok((x, y, z)),
+        _ => Err(invalid_data("Invalid MetaImage DimSize")),
+    }
+}
+
+fn parse_triplet<T: Real>(value: &str) -> io::Result<(T, T, T)> {
+    let values = value
+        .split_whitespace()
+        .map(|v| {
+            v.parse()
+                .map_err(|_| invalid_data("Invalid MetaImage vector"))
+        })
+        .collect::<io::Result<Vec<T>>>()?;
+    match values.as_slice() {
+        &[x, y, z] => Ok((x, y, z)),
+        _ => Err(invalid_data("Invalid MetaImage vector")),
+    }
+}
+
+// Axis directions from a matrix listing them one after another, or `None` for identity
+fn parse_axes<T: Real>(value: &str) -> io::Result<Option<[(T, T, T); 3]>> {
+    let values = value
+        .split_whitespace()
+        .map(|v| {
+            v.parse()
+                .map_err(|_| invalid_data("Invalid MetaImage TransformMatrix"))
+        })
+        .collect::<io::Result<Vec<T>>>()?;
+    if values.len() != 9 {
+        return Err(invalid_data("Invalid MetaImage TransformMatrix"));
+    }
+    let axes = [
+        (values[0], values[1], values[2]),
+        (values[3], values[4], values[5]),
+        (values[6], values[7], values[8]),
+    ];
+    let (zero, one) = (T::zero(), T::one());
+    if axes == [(one, zero, zero), (zero, one, zero), (zero, zero, one)] {
+        Ok(None)
+    } else {
+        Ok(Some(axes))
+    }
+}
+
+fn parse_bool(value: &str) -> io::Result<bool> {
+    match value {
+        "True" | "true" => Ok(true),
+        "False" | "false" => Ok(false),
+        _ => Err(invalid_data("Invalid MetaImage boolean")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use crate::field::ScalarField;
+    use crate::raw::tests::test_dir;
+
+    // x + 10y + 100z on a 3x4x5 grid, as little endian f32
+    fn samples() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for z in 0..5 {
+            for y in 0..4 {
+                for x in 0..3 {
+                    bytes.extend_from_slice(&((x + 10 * y + 100 * z) as f32).to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    const HEADER: &str = "ObjectType = Image\n\
+                          NDims = 3\n\
+                          BinaryData = True\n\
+                          BinaryDataByteOrderMSB = False\n\
+                          TransformMatrix = 1 0 0 0 1 0 0 0 1\n\
+                          Offset = 1 2 -3.5\n\
+                          CenterOfRotation = 0 0 0\n\
+                          AnatomicalOrientation = RAI\n\
+                          ElementSpacing = 0.5 0.25 1.5\n\
+                          DimSize = 3 4 5\n\
+                          ElementType = MET_FLOAT\n";
+
+    fn check(field: &Field<f64>, bounds: &Bounds<f64>) {
+        assert_eq!((3, 4, 5), field.corner_count());
+        assert_eq!(321.0, field.f(1, 2, 3));
+        assert_eq!((1.0, 2.0, -3.5), bounds.min);
+        assert_eq!((2.0, 2.75, 2.5), bounds.max);
+    }
+
+    #[test]
+    fn test_local() {
+        let dir = test_dir("metaimage_local");
+        for &compressed in &[false, true] {
+            let path = dir.join("volume.mha");
+            let mut file = File::create(&path).unwrap();
+            let compressed_data = if compressed { "True" } else { "False" };
+            write!(
+                file,
+                "{}CompressedData = {}\nElementDataFile = LOCAL\n",
+                HEADER, compressed_data
+            )
+            .unwrap();
+            if compressed {
+                let mut encoder = ZlibEncoder::new(file, Compression::default());
+                encoder.write_all(&samples()).unwrap();
+                encoder.finish().unwrap();
+            } else {
+                file.write_all(&samples()).unwrap();
+            }
+
+            let (field, bounds) = Field::read_metaimage(&path).unwrap();
+            check(&field, &bounds);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_too_much_data() {
+        // Compressed data inflating to far more than the header says fails without being read to
+        // the end
+        let dir = test_dir("metaimage_too_much");
+        let path = dir.join("volume.mha");
+        let mut file = File::create(&path).unwrap();
+        write!(
+            file,
+            "{}CompressedData = True\nElementDataFile = LOCAL\n",
+            HEADER
+        )
+        .unwrap();
+        let mut encoder = ZlibEncoder::new(file, Compression::default());
+        encoder.write_all(&samples()).unwrap();
+        encoder.write_all(&vec![0; 1 << 24]).unwrap();
+        encoder.finish().unwrap();
+        let error = Field::<f32>::read_metaimage(&path).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_separate_file() {
+        let dir = test_dir("metaimage_separate");
+        fs::write(dir.join("volume.raw"), samples()).unwrap();
+        let header = format!("{}ElementDataFile = volume.raw\n", HEADER);
+        fs::write(dir.join("volume.mhd"), header).unwrap();
+        let (field, bounds) = Field::read_metaimage(dir.join("volume.mhd")).unwrap();
+        check(&field, &bounds);
+
+        fs::write(dir.join("volume.raw"), &samples()[4..]).unwrap();
+        assert!(Field::<f32>::read_metaimage(dir.join("volume.mhd")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotated() {
+        let dir = test_dir("metaimage_rotated");
+        // x axis along world y, y axis along world -x
+        let header = format!(
+            "{}ElementDataFile = volume.raw\n",
+            HEADER.replace("1 0 0 0 1 0 0 0 1", "0 1 0 -1 0 0 0 0 1")
+        );
+        fs::write(dir.join("volume.raw"), samples()).unwrap();
+        fs::write(dir.join("volume.mhd"), header).unwrap();
+        let (field, bounds) = Field::<f64>::read_metaimage(dir.join("volume.mhd")).unwrap();
+        assert_eq!(Some((0.5, 0.25, 1.5)), field.spacing());
+        assert_eq!(Some((0.75, 2.5, -2.0)), field.position(1, 1, 1));
+        assert_eq!((0.25, 2.0, -3.5), bounds.min);
+        assert_eq!((1.0, 3.0, 2.5), bounds.max);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let read = |header: &str| MetaImageHeader::<f32>::read(&mut header.as_bytes());
+        let valid =
+            "NDims = 3\nDimSize = 2 2 2\nElementType = MET_UCHAR\nElementDataFile = LOCAL\n";
+        assert!(read(valid).is_ok());
+        assert!(read(&valid.replace("NDims = 3", "NDims = 2")).is_err());
+        assert!(read(&valid.replace("MET_UCHAR", "MET_LONG")).is_err());
+        assert!(read(&valid.replace("ElementDataFile = LOCAL\n", "")).is_err());
+        assert!(read(&format!("TransformMatrix = 1 0 0 0 1 0\n{}", valid)).is_err());
+    }
+}
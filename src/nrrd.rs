@@ -0,0 +1,384 @@
+
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::error::invalid_data;
+use crate::field::axes_transform;
+use crate::field::Bounds;
+use crate::field::Field;
+use crate::raw::read_samples;
+use crate::raw::Endianness;
+use crate::raw::SampleType;
+use crate::real::Real;
+
+impl<T: Real> Field<T> {
+    // Reads a three dimensional volume from an NRRD file (.nrrd or .nhdr with detached data) in raw
+    // or gzip encoding. The field is placed in world space by `space origin` and `spacings` or
+    // `space directions` in the header, and the returned bounds enclose its grid.
+    pub fn read_nrrd<P: AsRef<Path>>(path: P) -> io::Result<(Field<T>, Bounds<T>)> {
+        let path = path.as_ref();
+        let mut r = BufReader::new(File::open(path)?);
+        let header: NrrdHeader<T> = NrrdHeader::read(&mut r)?;
+        let data: Box<dyn Read> = match header.data_file {
+            // Detached data file is relative to the header
+            Some(ref file) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                Box::new(BufReader::new(File::open(dir.join(file))?))
+            }
+            None => Box::new(r),
+        };
+        let data: Box<dyn Read> = if header.gzip {
+            Box::new(MultiGzDecoder::new(data))
+        } else {
+            data
+        };
+        let samples = read_samples(data, header.sizes, header.sample_type, header.endianness)?;
+        let field = Field::try_from_flat(header.sizes, samples)?.with_spacing(header.spacing);
+        let field = match header.axes {
+            Some(ref axes) => field.with_transform(axes_transform(axes, header.origin)),
+            None => field.with_origin(header.origin),
+        };
+        let bounds = field.bounds().unwrap();
+        Ok((field, bounds))
+    }
+}
+
+struct NrrdHeader<T> {
+    sample_type: SampleType,
+    sizes: (usize, usize, usize),
+    endianness: Endianness,
+    gzip: bool,
+    origin: (T, T, T),
+    spacing: (T, T, T),
+    // Unit vectors of the grid axes, if they aren't aligned with world axes
+    axes: Option<[(T, T, T); 3]>,
+    data_file: Option<String>,
+}
+
+impl<T: Real> NrrdHeader<T> {
+    // Reads header fields up to the blank line before attached data, or to the end of a detached
+    // header
+    fn read<R: BufRead>(r: &mut R) -> io::Result<NrrdHeader<T>> {
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if !line.starts_with("NRRD000") {
+            return Err(invalid_data("Not an NRRD file"));
+        }
+
+        let mut sample_type = None;
+        let mut sizes = None;
+        let mut header = NrrdHeader {
+            sample_type: SampleType::U8,
+            sizes: (0, 0, 0),
+            endianness: Endianness::Little,
+            gzip: false,
+            origin: (T::zero(), T::zero(), T::zero()),
+            spacing: (T::one(), T::one(), T::one()),
+            axes: None,
+            data_file: None,
+        };
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                break;
+            }
+            // Comments and key/value pairs
+            if line.starts_with('#') || line.contains(":=") {
+                continue;
+            }
+            let (field, value) = match line.find(": ") {
+                Some(i) => (&line[..i], line[(i + 2)..].trim()),
+                None => return Err(invalid_data("Invalid NRRD header line")),
+            };
+            match field {
+                "type" => sample_type = Some(parse_type(value)?),
+                "dimension" if value != "3" => {
+                    return Err(invalid_data("Only three dimensional NRRD is supported"))
+                }
+                "sizes" => sizes = Some(parse_sizes(value)?),
+                "endian" => {
+                    header.endianness = match value {
+                        "little" => Endianness::Little,
+                        "big" => Endianness::Big,
+                        _ => return Err(invalid_data("Invalid NRRD endian")),
+                    }
+                }
+                "encoding" => {
+                    header.gzip = match value {
+                        "raw" => false,
+                        "gzip" | "gz" => true,
+                        _ => return Err(invalid_data("Unsupported NRRD encoding")),
+                    }
+                }
+                "spacings" => header.spacing = parse_triplet(value.split_whitespace())?,
+                "space directions" => {
+                    let (spacing, axes) = parse_directions(value)?;
+                    header.spacing = spacing;
+                    header.axes = axes;
+                }
+                "space origin" => header.origin = parse_triplet(vector_components(value))?,
+                "data file" | "datafile" => {
+                    if value.starts_with("LIST") || value.contains('%') {
+                        return Err(invalid_data("Multiple NRRD data files are not supported"));
+                    }
+                    header.data_file = Some(value.to_string());
+                }
+                "byte skip" | "line skip" if value != "0" => {
+                    return Err(invalid_data("NRRD byte and line skip are not supported"))
+                }
+                _ => {}
+            }
+        }
+        header.sample_type = sample_type.ok_or_else(|| invalid_data("Missing NRRD type"))?;
+        header.sizes = sizes.ok_or_else(|| invalid_data("Missing NRRD sizes"))?;
+        Ok(header)
+    }
+}
+
+fn parse_type(value: &str) -> io::Result<SampleType> {
+    Ok(match value {
+        "uchar" | "unsigned char" | "uint8" | "uint8_t" => SampleType::U8,
+        "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+            SampleType::U16
+        }
+        "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+            SampleType::I16
+        }
+        "float" => SampleType::F32,
+        "double" => SampleType::F64,
+        _ => return Err(invalid_data("Unsupported NRRD type")),
+    })
+}
+
+fn parse_sizes(value: &str) -> io::Result<(usize, usize, usize)> {
+    let sizes = value
+        .split_whitespace()
+        .map(|s| s.parse().map_err(|_| invalid_data("Invalid NRRD sizes")))
+        .collect::<io::Result<Vec<usize>>>()?;
+    match sizes.as_slice() {
+        &[x, y, z] if x > 1 && y > 1 && z > 1 => Ok((x, y, z)),
+        _ => Err(invalid_data("Invalid NRRD sizes")),
+    }
+}
+
+// Components of a vector written as `(x,y,z)`
+fn vector_components(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+}
+
+fn parse_triplet<'a, T: Real, I: Iterator<Item = &'a str>>(values: I) -> io::Result<(T, T, T)> {
+    let values = values
+        .map(|v| {
+            v.trim()
+                .parse()
+                .map_err(|_| invalid_data("Invalid NRRD vector"))
+        })
+        .collect::<io::Result<Vec<T>>>()?;
+    match values.as_slice() {
+        &[x, y, z] => Ok((x, y, z)),
+        _ => Err(invalid_data("Invalid NRRD vector")),
+    }
+}
+
+// Spacing from space directions, e.g. `(0.5,0,0) (0,0.5,0) (0,0,2)`, and unit vectors of the axes
+// unless they are aligned with world axes
+type Directions<T> = ((T, T, T), Option<[(T, T, T); 3]>);
+
+fn parse_directions<T: Real>(value: &str) -> io::Result<Directions<T>> {
+    let directions = value
+        .split_whitespace()
+        .map(|d| parse_triplet(vector_components(d)))
+        .collect::<io::Result<Vec<(T, T, T)>>>()?;
+    match *directions.as_slice() {
+        [(x, y0, z0), (x1, y, z1), (x2, y2, z)]
+            if [y0, z0, x1, z1, x2, y2].iter().all(|&c| c == T::zero()) =>
+        {
+            Ok(((x, y, z), None))
+        }
+        [a, b, c] => {
+            let length = |v: (T, T, T)| (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+            let unit = |v: (T, T, T), l: T| (v.0 / l, v.1 / l, v.2 / l);
+            let spacing = (length(a), length(b), length(c));
+            if [spacing.0, spacing.1, spacing.2].contains(&T::zero()) {
+                return Err(invalid_data("Invalid NRRD space directions"));
+            }
+            let axes = [unit(a, spacing.0), unit(b, spacing.1), unit(c, spacing.2)];
+            Ok((spacing, Some(axes)))
+        }
+        _ => Err(invalid_data("Invalid NRRD space directions")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::field::ScalarField;
+    use crate::raw::tests::test_dir;
+    use crate::tessellator::create_mesh;
+    use crate::tessellator::tests::volume;
+
+    // Distance from the center of a 5x6x7 volume, as big endian u16
+    fn sphere_samples() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for z in 0..7 {
+            for y in 0..6 {
+                for x in 0..5 {
+                    let d = (x as f32 - 2.0).powi(2)
+                        + (y as f32 - 2.5).powi(2)
+                        + (z as f32 - 3.0).powi(2);
+                    bytes.extend_from_slice(&((d.sqrt() * 100.0) as u16).to_be_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    const HEADER: &str = "NRRD0004\n\
+                          # Complete NRRD file format specification at:\n\
+                          type: unsigned short\n\
+                          dimension: 3\n\
+                          space: left-posterior-superior\n\
+                          sizes: 5 6 7\n\
+                          space directions: (0.5,0,0) (0,0.5,0) (0,0,2)\n\
+                          kinds: domain domain domain\n\
+                          endian: big\n\
+                          space origin: (-1,-1.25,10)\n\
+                          scanner:=test\n";
+
+    fn check(field: &Field, bounds: &Bounds) {
+        assert_eq!((5, 6, 7), field.corner_count());
+        assert_eq!(50.0, field.f(2, 2, 3));
+        assert_eq!(304.0, field.f(2, 2, 0));
+        assert_eq!((-1.0, -1.25, 10.0), bounds.min);
+        assert_eq!((1.0, 1.25, 22.0), bounds.max);
+    }
+
+    #[test]
+    fn test_attached() {
+        let dir = test_dir("nrrd_attached");
+        for &gzip in &[false, true] {
+            let path = dir.join("volume.nrrd");
+            let mut file = File::create(&path).unwrap();
+            let encoding = if gzip { "gzip" } else { "raw" };
+            writeln!(file, "{}encoding: {}\n", HEADER, encoding).unwrap();
+            if gzip {
+                let mut encoder = GzEncoder::new(file, Compression::default());
+                encoder.write_all(&sphere_samples()).unwrap();
+                encoder.finish().unwrap();
+            } else {
+                file.write_all(&sphere_samples()).unwrap();
+            }
+
+            let (field, bounds) = Field::read_nrrd(&path).unwrap();
+            check(&field, &bounds);
+
+            // Sphere of radius 150 samples, i.e. 0.75 in world units along x
+            let iso = field.as_slice().iter().map(|&v| v - 150.0).collect();
+            let field = Field::from_flat(field.corner_count(), iso);
+            let mesh = create_mesh(&field, &bounds.min, &bounds.max).unwrap();
+            assert!(!mesh.1.is_empty());
+            assert!(mesh.0.iter().all(|v| v.0.abs() < 0.76));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_too_much_data() {
+        // Compressed data inflating to far more than the header says fails without being read to
+        // the end
+        let dir = test_dir("nrrd_too_much");
+        let path = dir.join("volume.nrrd");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}encoding: gzip\n", HEADER).unwrap();
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&sphere_samples()).unwrap();
+        encoder.write_all(&vec![0; 1 << 24]).unwrap();
+        encoder.finish().unwrap();
+        let error = Field::<f32>::read_nrrd(&path).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_detached() {
+        let dir = test_dir("nrrd_detached");
+        fs::write(dir.join("volume.raw"), sphere_samples()).unwrap();
+        let header = format!("{}encoding: raw\ndata file: volume.raw\n", HEADER);
+        fs::write(dir.join("volume.nhdr"), header).unwrap();
+        let (field, bounds) = Field::read_nrrd(dir.join("volume.nhdr")).unwrap();
+        check(&field, &bounds);
+
+        // Truncated data
+        fs::write(dir.join("volume.raw"), &sphere_samples()[1..]).unwrap();
+        assert!(Field::<f32>::read_nrrd(dir.join("volume.nhdr")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_oblique() {
+        let dir = test_dir("nrrd_oblique");
+        let path = dir.join("volume.nrrd");
+        let mut file = File::create(&path).unwrap();
+        // Axes rotated 90 degrees around z and mirrored along z
+        let header = HEADER.replace(
+            "(0.5,0,0) (0,0.5,0) (0,0,2)",
+            "(0,0.5,0) (-0.5,0,0) (0,0,-2)",
+        );
+        writeln!(file, "{}encoding: raw\n", header).unwrap();
+        file.write_all(&sphere_samples()).unwrap();
+        drop(file);
+
+        let (field, bounds) = Field::<f64>::read_nrrd(&path).unwrap();
+        assert_eq!(Some((0.5, 0.5, 2.0)), field.spacing());
+        assert_eq!(Some((-1.0, -1.25, 10.0)), field.position(0, 0, 0));
+        assert_eq!(Some((-1.5, 0.25, 8.0)), field.position(3, 1, 1));
+        assert_eq!((-3.5, -1.25, -2.0), bounds.min);
+        assert_eq!((-1.0, 0.75, 10.0), bounds.max);
+
+        // Mirrored grid still gives outward facing triangles, i.e. positive signed volume
+        let iso = field.as_slice().iter().map(|&v| v - 150.0).collect();
+        let field = Field::from_flat(field.corner_count(), iso)
+            .with_spacing(field.spacing().unwrap())
+            .with_transform(*field.transform().unwrap());
+        let mesh = create_mesh(&field, &(0.0, 0.0, 0.0), &(0.0, 0.0, 0.0)).unwrap();
+        assert!(mesh.is_closed());
+        assert!(volume(&mesh) > 0.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let read = |header: &str| NrrdHeader::<f32>::read(&mut header.as_bytes());
+        assert!(read("NRRD0004\ntype: float\ndimension: 3\nsizes: 2 2 2\n").is_ok());
+        assert!(read("P6\n").is_err());
+        assert!(read("NRRD0004\ntype: float\ndimension: 2\nsizes: 2 2\n").is_err());
+        assert!(read("NRRD0004\ntype: float\nsizes: 2 2 2\nencoding: bzip2\n").is_err());
+        assert!(read(
+            "NRRD0004\ntype: float\nsizes: 2 2 2\n\
+                      space directions: (1,0,0) (0,1,0)\n"
+        )
+        .is_err());
+        assert!(read("NRRD0004\nsizes: 2 2 2\n").is_err());
+    }
+}
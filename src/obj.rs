@@ -0,0 +1,178 @@
+
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+
+use crate::error::invalid_data;
+use crate::mesh::Mesh;
+use crate::mesh::Normal;
+use crate::mesh::Triangle;
+use crate::mesh::Vertex;
+use crate::real::Real;
+
+impl<T: Real> Mesh<T> {
+    // Writes the mesh in Wavefront OBJ format. Normals are written as `vn` records when the mesh
+    // has them, with the same index as the vertex.
+    pub fn write_obj<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_obj_groups(w, &[(0..self.1.len()).collect()], false)
+    }
+
+    // Same as `write_obj`, but triangles of each connected component are put in a separate group
+    // named `component_N`.
+    pub fn write_obj_grouped<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_obj_groups(w, &self.components(), true)
+    }
+
+    fn write_obj_groups<W: Write>(
+        &self,
+        w: &mut W,
+        groups: &[Vec<usize>],
+        named: bool,
+    ) -> io::Result<()> {
+        for v in &self.0 {
+            writeln!(w, "v {} {} {}", v.0, v.1, v.2)?;
+        }
+        for n in &self.2 {
+            writeln!(w, "vn {} {} {}", n.0, n.1, n.2)?;
+        }
+        for (i, group) in groups.iter().enumerate() {
+            if named {
+                writeln!(w, "g component_{}", i)?;
+            }
+            for &t in group {
+                // OBJ indices are 1-based
+                let Triangle(i0, i1, i2) = self.1[t];
+                let (i0, i1, i2) = (i0 + 1, i1 + 1, i2 + 1);
+                if self.has_normals() {
+                    writeln!(w, "f {0}//{0} {1}//{1} {2}//{2}", i0, i1, i2)?;
+                } else {
+                    writeln!(w, "f {} {} {}", i0, i1, i2)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reads a mesh from Wavefront OBJ data. Polygons are triangulated as fans, and texture
+    // coordinates, groups and other records are ignored. Normals are read only when there is one
+    // for each vertex.
+    pub fn read_obj<R: BufRead>(r: R) -> io::Result<Mesh<T>> {
+        let mut verts = Vec::new();
+        let mut normals = Vec::new();
+        let mut tris = Vec::new();
+        for line in r.lines() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let (x, y, z) = parse_triplet(&mut tokens)?;
+                    verts.push(Vertex(x, y, z));
+                }
+                Some("vn") => {
+                    let (x, y, z) = parse_triplet(&mut tokens)?;
+                    normals.push(Normal(x, y, z));
+                }
+                Some("f") => {
+                    let face = tokens
+                        .map(|t| parse_index(t, verts.len()))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if face.len() < 3 {
+                        return Err(invalid_data("Face with less than 3 vertices"));
+                    }
+                    for i in 1..(face.len() - 1) {
+                        tris.push(Triangle(face[0], face[i], face[i + 1]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if normals.len() != verts.len() {
+            normals.clear();
+        }
+        Ok(Mesh(verts, tris, normals))
+    }
+}
+
+fn parse_triplet<'a, T: Real, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+) -> io::Result<(T, T, T)> {
+    let mut parse = || {
+        tokens
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| invalid_data("Invalid coordinate"))
+    };
+    Ok((parse()?, parse()?, parse()?))
+}
+
+// Parses vertex index from a face element of form `v`, `v/vt`, `v//vn` or `v/vt/vn`. Negative
+// indices are relative to the end of the vertex list.
+fn parse_index(token: &str, vert_count: usize) -> io::Result<usize> {
+    let index: isize = token
+        .split('/')
+        .next()
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| invalid_data("Invalid face index"))?;
+    let index = if index < 0 {
+        vert_count as isize + index
+    } else {
+        index - 1
+    };
+    if index < 0 || index as usize >= vert_count {
+        return Err(invalid_data("Face index out of range"));
+    }
+    Ok(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tessellator::tests::sphere_mesh;
+
+    fn round_trip(mesh: &Mesh) -> Mesh {
+        let mut obj = Vec::new();
+        mesh.write_obj(&mut obj).unwrap();
+        Mesh::read_obj(&obj[..]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut mesh = sphere_mesh(0.98);
+        assert!(!mesh.1.is_empty());
+
+        let read = round_trip(&mesh);
+        assert_eq!(mesh.0, read.0);
+        assert_eq!(mesh.1, read.1);
+        assert!(!read.has_normals());
+
+        mesh.compute_vertex_normals();
+        let read = round_trip(&mesh);
+        assert_eq!(mesh.0, read.0);
+        assert_eq!(mesh.1, read.1);
+        assert_eq!(mesh.2, read.2);
+    }
+
+    #[test]
+    fn test_grouped() {
+        let mesh = sphere_mesh(0.98);
+        let mut obj = Vec::new();
+        mesh.write_obj_grouped(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(1, obj.lines().filter(|l| l.starts_with("g ")).count());
+        assert!(obj.contains("g component_0\n"));
+
+        let read: Mesh = Mesh::read_obj(obj.as_bytes()).unwrap();
+        assert_eq!(mesh.1, read.1);
+    }
+
+    #[test]
+    fn test_read() {
+        let obj = "# quad\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 -2/1 -1/1\n";
+        let mesh: Mesh = Mesh::read_obj(obj.as_bytes()).unwrap();
+        assert_eq!(vec![Triangle(0, 1, 2), Triangle(0, 2, 3)], mesh.1);
+
+        assert!(Mesh::<f32>::read_obj("v 0 0 0\nf 1 2 3\n".as_bytes()).is_err());
+        assert!(Mesh::<f32>::read_obj("v 0 0 x\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+
+use rayon::prelude::*;
+
+use crate::error::Error;
+use crate::field::ScalarField;
+use crate::mesh::Mesh;
+use crate::mesh::Triangle;
+use crate::real::Real;
+use crate::tessellator::validate;
+use crate::tessellator::MeshBuilder;
+use crate::tessellator::MeshOptions;
+
+// Same as `create_mesh_with`, but slabs of cubes are tessellated in parallel. Vertices on the
+// boundaries between slabs are welded afterwards, so the resulting mesh is identical to the one
+// created by `create_mesh_with`.
+pub fn create_mesh_parallel<T: Real, F: ScalarField<T> + Sync + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    options: &MeshOptions<T>,
+) -> Result<Mesh<T>, Error> {
+    validate(field, min_bound, max_bound)?;
+    // A few blocks per thread to balance uneven work between blocks
+    let block_count = rayon::current_num_threads() * 4;
+    let block_size = field.cube_count().2.div_ceil(block_count);
+    Ok(create_mesh_blocks(field, min_bound, max_bound, options, block_size.max(1)))
+}
+
+// Result of tessellating a block of slabs, with vertices on its bottom and top corner layers
+struct Block<T> {
+    mesh: Mesh<T>,
+    bottom: Vec<Option<usize>>,
+    top: Vec<Option<usize>>,
+}
+
+fn create_mesh_blocks<T: Real, F: ScalarField<T> + Sync + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    options: &MeshOptions<T>,
+    block_size: usize,
+) -> Mesh<T> {
+    let cube_count = field.cube_count();
+    let starts: Vec<_> = (0..cube_count.2).step_by(block_size).collect();
+    let blocks: Vec<Block<T>> = starts
+        .par_iter()
+        .map(|&z0| {
+            let z1 = (z0 + block_size).min(cube_count.2);
+            let mut builder = MeshBuilder::new(options, min_bound, max_bound, &cube_count);
+            builder.march_slab(field, z0);
+            let bottom = builder.layer_vertices(z0);
+            for z in (z0 + 1)..z1 {
+                builder.march_slab(field, z);
+            }
+            let top = builder.layer_vertices(z1);
+            Block {
+                mesh: builder.finish(),
+                bottom,
+                top,
+            }
+        })
+        .collect();
+
+    // Stitch blocks in order. Vertices on the bottom layer of a block were already created by the
+    // block below it, so vertices end up in the same order as when marching serially.
+    let mut verts = Vec::new();
+    let mut tris = Vec::new();
+    let mut normals = Vec::new();
+    let mut prev_top = Vec::new();
+    for Block { mesh, bottom, top } in blocks {
+        let Mesh(block_verts, block_tris, block_normals) = mesh;
+        let mut remap = vec![None; block_verts.len()];
+        for (e, v) in bottom.into_iter().enumerate() {
+            if let Some(v) = v {
+                remap[v] = prev_top.get(e).cloned().unwrap_or(None);
+            }
+        }
+        for (i, vert) in block_verts.into_iter().enumerate() {
+            if remap[i].is_none() {
+                remap[i] = Some(verts.len());
+                verts.push(vert);
+                if options.normals {
+                    normals.push(block_normals[i].clone());
+                }
+            }
+        }
+        let remap: Vec<usize> = remap.into_iter().map(|v| v.unwrap()).collect();
+        tris.extend(
+            block_tris
+                .into_iter()
+                .map(|Triangle(i0, i1, i2)| Triangle(remap[i0], remap[i1], remap[i2])),
+        );
+        prev_top = top.into_iter().map(|v| v.map(|v| remap[v])).collect();
+    }
+    Mesh(verts, tris, normals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Field;
+    use crate::tessellator::create_mesh_with;
+    use crate::tessellator::tests::noise_field;
+    use crate::tessellator::Tessellation;
+
+    #[test]
+    fn test_same_as_serial() {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let field = Field::from_closure(
+            |x, y, z| (x * x + y * y + z * z).sqrt() - 0.8 + 0.1 * (5.0 * x).sin() * z,
+            &min,
+            &max,
+            &(20, 21, 22),
+        );
+        let cases = [
+            (false, Tessellation::Classic),
+            (true, Tessellation::Classic),
+            (true, Tessellation::Tetrahedra),
+        ];
+        for &(normals, tessellation) in &cases {
+            let options = MeshOptions {
+                normals,
+                tessellation,
+                ..MeshOptions::default()
+            };
+            let expected = create_mesh_with(&field, &min, &max, &options).unwrap();
+            for &block_size in &[1, 2, 5, 22, 100] {
+                let mesh = create_mesh_blocks(&field, &min, &max, &options, block_size);
+                assert_eq!(expected.0, mesh.0);
+                assert_eq!(expected.1, mesh.1);
+                assert_eq!(expected.2, mesh.2);
+            }
+            let mesh = create_mesh_parallel(&field, &min, &max, &options).unwrap();
+            assert_eq!(expected.0, mesh.0);
+            assert_eq!(expected.1, mesh.1);
+        }
+
+        // Bounds are only checked for fields that don't place themselves
+        let field = Field::from_flat(field.corner_count(), field.as_slice().to_vec());
+        let result = create_mesh_parallel(&field, &max, &min, &MeshOptions::default());
+        assert_eq!(Some(Error::InvalidBounds), result.err());
+    }
+
+    #[test]
+    fn test_mc33_same_as_serial() {
+        // Random values with many ambiguous cube interiors, where MC33 places vertices inside
+        // cubes next to block boundaries
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let field = noise_field(12);
+        for &normals in &[false, true] {
+            let options = MeshOptions {
+                normals,
+                tessellation: Tessellation::Mc33,
+                ..MeshOptions::default()
+            };
+            let expected = create_mesh_with(&field, &min, &max, &options).unwrap();
+            assert!(expected.is_closed());
+            for &block_size in &[1, 2, 5, 100] {
+                let mesh = create_mesh_blocks(&field, &min, &max, &options, block_size);
+                assert_eq!(expected.0, mesh.0);
+                assert_eq!(expected.1, mesh.1);
+                assert_eq!(expected.2, mesh.2);
+            }
+            let mesh = create_mesh_parallel(&field, &min, &max, &options).unwrap();
+            assert_eq!(expected.0, mesh.0);
+            assert_eq!(expected.1, mesh.1);
+        }
+    }
+}
@@ -0,0 +1,638 @@
+
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::mem;
+
+use crate::error::invalid_data;
+use crate::mesh::Mesh;
+use crate::mesh::Normal;
+use crate::mesh::Triangle;
+use crate::mesh::Vertex;
+use crate::real::Real;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+// Per-vertex data stored in a PLY file alongside the mesh. Colors are either empty or one per
+// vertex, and each named scalar attribute (e.g. field value or gradient magnitude) has one value
+// per vertex.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct VertexAttributes<T = f32> {
+    pub colors: Vec<(u8, u8, u8)>,
+    pub scalars: Vec<(String, Vec<T>)>,
+}
+
+impl<T: Real> Mesh<T> {
+    // Writes vertices, triangles and normals (if any) in PLY format
+    pub fn write_ply<W: Write>(&self, w: &mut W, format: PlyFormat) -> io::Result<()> {
+        self.write_ply_with(w, format, &VertexAttributes::default())
+    }
+
+    // Same as `write_ply`, with additional per-vertex colors and scalar attributes
+    pub fn write_ply_with<W: Write>(
+        &self,
+        w: &mut W,
+        format: PlyFormat,
+        attributes: &VertexAttributes<T>,
+    ) -> io::Result<()> {
+        let vert_count = self.0.len();
+        check_attributes(attributes, vert_count)?;
+        // Faces are written as `uint` lists
+        if self
+            .1
+            .iter()
+            .any(|t| t.0.max(t.1).max(t.2) > u32::MAX as usize)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Vertex index too large for PLY",
+            ));
+        }
+
+        let real = PlyType::of_real::<T>().name();
+        writeln!(w, "ply")?;
+        match format {
+            PlyFormat::Ascii => writeln!(w, "format ascii 1.0")?,
+            PlyFormat::BinaryLittleEndian => writeln!(w, "format binary_little_endian 1.0")?,
+        }
+        writeln!(w, "element vertex {}", vert_count)?;
+        let mut properties = vec!["x", "y", "z"];
+        if self.has_normals() {
+            properties.extend(&["nx", "ny", "nz"]);
+        }
+        for p in properties {
+            writeln!(w, "property {} {}", real, p)?;
+        }
+        if !attributes.colors.is_empty() {
+            for p in &["red", "green", "blue"] {
+                writeln!(w, "property uchar {}", p)?;
+            }
+        }
+        for (name, _) in &attributes.scalars {
+            writeln!(w, "property {} {}", real, name)?;
+        }
+        writeln!(w, "element face {}", self.1.len())?;
+        writeln!(w, "property list uchar uint vertex_indices")?;
+        writeln!(w, "end_header")?;
+
+        let mut out = PlyWriter {
+            w,
+            format,
+            first: true,
+        };
+        for (i, v) in self.0.iter().enumerate() {
+            out.reals(&[v.0, v.1, v.2])?;
+            if let Some(n) = self.2.get(i) {
+                out.reals(&[n.0, n.1, n.2])?;
+            }
+            if let Some(&(r, g, b)) = attributes.colors.get(i) {
+                out.bytes(&[r, g, b])?;
+            }
+            for (_, values) in &attributes.scalars {
+                out.reals(&[values[i]])?;
+            }
+            out.end_element()?;
+        }
+        for t in &self.1 {
+            out.bytes(&[3])?;
+            out.indices(&[t.0, t.1, t.2])?;
+            out.end_element()?;
+        }
+        Ok(())
+    }
+
+    // Reads a mesh from PLY data in ASCII or binary little-endian format. Polygons are
+    // triangulated as fans, and all properties other than positions and normals are ignored.
+    pub fn read_ply<R: BufRead>(r: R) -> io::Result<Mesh<T>> {
+        Mesh::read_ply_with_attributes(r).map(|(mesh, _)| mesh)
+    }
+
+    // Same as `read_ply`, also returning vertex colors and other scalar vertex properties
+    pub fn read_ply_with_attributes<R: BufRead>(
+        mut r: R,
+    ) -> io::Result<(Mesh<T>, VertexAttributes<T>)> {
+        let header = read_header(&mut r)?;
+        let mut input = PlyReader {
+            r,
+            format: header.format,
+            tokens: Vec::new(),
+        };
+
+        let mut verts = Vec::new();
+        let mut normals = Vec::new();
+        let mut tris = Vec::new();
+        let mut attributes = VertexAttributes::default();
+        for element in &header.elements {
+            let is_vertex = element.name == "vertex";
+            let is_face = element.name == "face";
+            if is_vertex {
+                for p in &element.properties {
+                    if p.list.is_none() && !is_known_vertex_property(&p.name) {
+                        attributes.scalars.push((p.name.clone(), Vec::new()));
+                    }
+                }
+            }
+            for _ in 0..element.count {
+                let mut position = [T::zero(); 3];
+                let mut normal = [T::zero(); 3];
+                let mut color = [0u8; 3];
+                let mut scalar = 0;
+                for p in &element.properties {
+                    if let Some(count_type) = p.list {
+                        let count = input.index(count_type)?;
+                        let mut items = Vec::new();
+                        for _ in 0..count {
+                            items.push(input.index(p.ty)?);
+                        }
+                        let is_indices = p.name == "vertex_indices" || p.name == "vertex_index";
+                        if is_face && is_indices {
+                            if items.len() < 3 {
+                                return Err(invalid_data("Face with less than 3 vertices"));
+                            }
+                            for i in 1..(items.len() - 1) {
+                                tris.push(Triangle(items[0], items[i], items[i + 1]));
+                            }
+                        }
+                        continue;
+                    }
+                    if !is_vertex {
+                        input.value::<f64>(p.ty)?;
+                        continue;
+                    }
+                    match p.name.as_str() {
+                        "x" => position[0] = input.value(p.ty)?,
+                        "y" => position[1] = input.value(p.ty)?,
+                        "z" => position[2] = input.value(p.ty)?,
+                        "nx" => normal[0] = input.value(p.ty)?,
+                        "ny" => normal[1] = input.value(p.ty)?,
+                        "nz" => normal[2] = input.value(p.ty)?,
+                        "red" => color[0] = input.value::<f64>(p.ty)? as u8,
+                        "green" => color[1] = input.value::<f64>(p.ty)? as u8,
+                        "blue" => color[2] = input.value::<f64>(p.ty)? as u8,
+                        _ => {
+                            let v = input.value(p.ty)?;
+                            attributes.scalars[scalar].1.push(v);
+                            scalar += 1;
+                        }
+                    }
+                }
+                if is_vertex {
+                    verts.push(Vertex(position[0], position[1], position[2]));
+                    if element.has("nx") {
+                        normals.push(Normal(normal[0], normal[1], normal[2]));
+                    }
+                    if element.has("red") {
+                        attributes.colors.push((color[0], color[1], color[2]));
+                    }
+                }
+            }
+        }
+        if tris
+            .iter()
+            .any(|t| t.0 >= verts.len() || t.1 >= verts.len() || t.2 >= verts.len())
+        {
+            return Err(invalid_data("Face index out of range"));
+        }
+        Ok((Mesh(verts, tris, normals), attributes))
+    }
+}
+
+// Fails with `InvalidInput` unless colors are empty or one per vertex, and each scalar attribute
+// has one value per vertex. Attribute names must be single words that don't clash with the
+// positions, normals or colors, so that the file can be read back.
+pub fn check_attributes<T>(attributes: &VertexAttributes<T>, vert_count: usize) -> io::Result<()> {
+    if !attributes.colors.is_empty() && attributes.colors.len() != vert_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Got {} colors for {} vertices",
+                attributes.colors.len(),
+                vert_count
+            ),
+        ));
+    }
+    for (name, values) in &attributes.scalars {
+        if name.is_empty() || name.contains(char::is_whitespace) || is_known_vertex_property(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid attribute name {:?}", name),
+            ));
+        }
+        if values.len() != vert_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Got {} values of attribute {} for {} vertices",
+                    values.len(),
+                    name,
+                    vert_count
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_known_vertex_property(name: &str) -> bool {
+    ["x", "y", "z", "nx", "ny", "nz", "red", "green", "blue"].contains(&name)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PlyType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> io::Result<PlyType> {
+        Ok(match name {
+            "char" | "int8" => PlyType::Char,
+            "uchar" | "uint8" => PlyType::UChar,
+            "short" | "int16" => PlyType::Short,
+            "ushort" | "uint16" => PlyType::UShort,
+            "int" | "int32" => PlyType::Int,
+            "uint" | "uint32" => PlyType::UInt,
+            "float" | "float32" => PlyType::Float,
+            "double" | "float64" => PlyType::Double,
+            _ => return Err(invalid_data("Unknown property type")),
+        })
+    }
+
+    // Type matching the precision of `T`
+    fn of_real<T: Real>() -> PlyType {
+        if mem::size_of::<T>() == 4 {
+            PlyType::Float
+        } else {
+            PlyType::Double
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PlyType::Char => "char",
+            PlyType::UChar => "uchar",
+            PlyType::Short => "short",
+            PlyType::UShort => "ushort",
+            PlyType::Int => "int",
+            PlyType::UInt => "uint",
+            PlyType::Float => "float",
+            PlyType::Double => "double",
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::Char | PlyType::UChar => 1,
+            PlyType::Short | PlyType::UShort => 2,
+            PlyType::Int | PlyType::UInt | PlyType::Float => 4,
+            PlyType::Double => 8,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    ty: PlyType,
+    // Type of the item count, for list properties
+    list: Option<PlyType>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn has(&self, property: &str) -> bool {
+        self.properties.iter().any(|p| p.name == property)
+    }
+}
+
+struct Header {
+    format: PlyFormat,
+    elements: Vec<Element>,
+}
+
+fn read_header<R: BufRead>(r: &mut R) -> io::Result<Header> {
+    let mut line = String::new();
+    let mut next_line = |r: &mut R| -> io::Result<Vec<String>> {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Unexpected end of PLY header"));
+        }
+        Ok(line.split_whitespace().map(String::from).collect())
+    };
+
+    if next_line(r)? != ["ply"] {
+        return Err(invalid_data("Not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let tokens = next_line(r)?;
+        let tokens: Vec<&str> = tokens.iter().map(|t| t.as_str()).collect();
+        match tokens.as_slice() {
+            ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(PlyFormat::BinaryLittleEndian),
+            ["format", ..] => return Err(invalid_data("Unsupported PLY format")),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("Invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list: Some(PlyType::parse(count_type)?),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("Property outside of element"))?
+                    .properties
+                    .push(property);
+            }
+            ["property", ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list: None,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("Property outside of element"))?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+    Ok(Header {
+        format: format.ok_or_else(|| invalid_data("Missing PLY format"))?,
+        elements,
+    })
+}
+
+struct PlyReader<R> {
+    r: R,
+    format: PlyFormat,
+    // Remaining tokens of the current line in reverse order, for ASCII format
+    tokens: Vec<String>,
+}
+
+impl<R: BufRead> PlyReader<R> {
+    fn value<V: Real>(&mut self, ty: PlyType) -> io::Result<V> {
+        match self.format {
+            PlyFormat::Ascii => {
+                while self.tokens.is_empty() {
+                    let mut line = String::new();
+                    if self.r.read_line(&mut line)? == 0 {
+                        return Err(invalid_data("Unexpected end of PLY data"));
+                    }
+                    self.tokens = line.split_whitespace().rev().map(String::from).collect();
+                }
+                let token = self.tokens.pop().unwrap();
+                token.parse().map_err(|_| invalid_data("Invalid PLY value"))
+            }
+            PlyFormat::BinaryLittleEndian => {
+                let mut b = [0u8; 8];
+                self.r.read_exact(&mut b[..ty.size()])?;
+                let v = match ty {
+                    PlyType::Char => f64::from(b[0] as i8),
+                    PlyType::UChar => f64::from(b[0]),
+                    PlyType::Short => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    PlyType::UShort => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    PlyType::Int => f64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    PlyType::UInt => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    PlyType::Float => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    PlyType::Double => f64::from_le_bytes(b),
+                };
+                Ok(V::from_f64(v))
+            }
+        }
+    }
+
+    // Reads a list count or vertex index, which must be a non-negative integer
+    fn index(&mut self, ty: PlyType) -> io::Result<usize> {
+        let v = self.value::<f64>(ty)?;
+        if v >= 0.0 && v.fract() == 0.0 && v <= u32::MAX as f64 {
+            Ok(v as usize)
+        } else {
+            Err(invalid_data("Invalid PLY count or index"))
+        }
+    }
+}
+
+struct PlyWriter<'a, W: 'a> {
+    w: &'a mut W,
+    format: PlyFormat,
+    // Whether next value is the first one of an element, for ASCII format
+    first: bool,
+}
+
+impl<'a, W: Write> PlyWriter<'a, W> {
+    fn reals<T: Real>(&mut self, values: &[T]) -> io::Result<()> {
+        for &v in values {
+            match (self.format, PlyType::of_real::<T>()) {
+                (PlyFormat::Ascii, _) => self.text(v)?,
+                (_, PlyType::Float) => self.w.write_all(&(v.to_f64() as f32).to_le_bytes())?,
+                _ => self.w.write_all(&v.to_f64().to_le_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes(&mut self, values: &[u8]) -> io::Result<()> {
+        for &v in values {
+            match self.format {
+                PlyFormat::Ascii => self.text(v)?,
+                PlyFormat::BinaryLittleEndian => self.w.write_all(&[v])?,
+            }
+        }
+        Ok(())
+    }
+
+    fn indices(&mut self, values: &[usize]) -> io::Result<()> {
+        for &v in values {
+            match self.format {
+                PlyFormat::Ascii => self.text(v)?,
+                PlyFormat::BinaryLittleEndian => self.w.write_all(&(v as u32).to_le_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn text<V: ::std::fmt::Display>(&mut self, v: V) -> io::Result<()> {
+        if !self.first {
+            write!(self.w, " ")?;
+        }
+        self.first = false;
+        write!(self.w, "{}", v)
+    }
+
+    fn end_element(&mut self) -> io::Result<()> {
+        self.first = true;
+        if self.format == PlyFormat::Ascii {
+            writeln!(self.w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tessellator::tests::sphere_mesh;
+
+    fn sphere_with_attributes() -> (Mesh, VertexAttributes) {
+        let mut mesh = sphere_mesh(0.9);
+        mesh.compute_vertex_normals();
+        let attributes = VertexAttributes {
+            colors: mesh
+                .0
+                .iter()
+                .map(|v| ((v.0 * 100.0 + 128.0) as u8, (v.1 * 100.0 + 128.0) as u8, 7))
+                .collect(),
+            scalars: vec![
+                ("height".to_string(), mesh.0.iter().map(|v| v.2).collect()),
+                (
+                    "quality".to_string(),
+                    (0..mesh.0.len()).map(|i| i as f32 * 0.1).collect(),
+                ),
+            ],
+        };
+        (mesh, attributes)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (mesh, attributes) = sphere_with_attributes();
+        for &format in &[PlyFormat::Ascii, PlyFormat::BinaryLittleEndian] {
+            let mut ply = Vec::new();
+            mesh.write_ply_with(&mut ply, format, &attributes).unwrap();
+            let (read, read_attributes) = Mesh::read_ply_with_attributes(&ply[..]).unwrap();
+            assert_eq!(mesh.0, read.0);
+            assert_eq!(mesh.1, read.1);
+            assert_eq!(mesh.2, read.2);
+            assert_eq!(attributes, read_attributes);
+
+            let mut ply = Vec::new();
+            let plain = sphere_mesh(0.9);
+            plain.write_ply(&mut ply, format).unwrap();
+            let read: Mesh = Mesh::read_ply(&ply[..]).unwrap();
+            assert_eq!(plain.0, read.0);
+            assert_eq!(plain.1, read.1);
+            assert!(!read.has_normals());
+        }
+    }
+
+    #[test]
+    fn test_f64() {
+        let mesh = Mesh::new(
+            vec![
+                Vertex(0.1f64, 0.2, 0.3),
+                Vertex(1.0, 0.0, 0.0),
+                Vertex(0.0, 1.0, 0.0),
+            ],
+            vec![Triangle(0, 1, 2)],
+        );
+        let mut ply = Vec::new();
+        mesh.write_ply(&mut ply, PlyFormat::BinaryLittleEndian)
+            .unwrap();
+        let read: Mesh<f64> = Mesh::read_ply(&ply[..]).unwrap();
+        assert_eq!(mesh.0, read.0);
+    }
+
+    #[test]
+    fn test_read() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   comment quad with an extra element\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 1\n\
+                   property uchar flags\n\
+                   property list uchar int vertex_indices\n\
+                   element edge 1\n\
+                   property int vertex1\n\
+                   property int vertex2\n\
+                   end_header\n\
+                   0 0 0\n1 0 0\n1 1 0\n0 1 0\n\
+                   5 4 0 1 2 3\n\
+                   0 1\n";
+        let mesh: Mesh = Mesh::read_ply(ply.as_bytes()).unwrap();
+        assert_eq!(4, mesh.0.len());
+        assert_eq!(vec![Triangle(0, 1, 2), Triangle(0, 2, 3)], mesh.1);
+
+        let broken = ply.replace("4 0 1 2 3", "4 0 1 2 4");
+        assert!(Mesh::<f32>::read_ply(broken.as_bytes()).is_err());
+        let broken = ply.replace("ascii", "binary_big_endian");
+        assert!(Mesh::<f32>::read_ply(broken.as_bytes()).is_err());
+
+        // Negative and fractional counts and indices aren't truncated to valid ones
+        for &(from, to) in &[("4 0 1 2 3", "4 0 1 2 -1"), ("4 0 1 2 3", "-4 0 1 2 3")] {
+            let broken = ply.replace(from, to);
+            let error = Mesh::<f32>::read_ply(broken.as_bytes()).err().unwrap();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        }
+        let broken = ply
+            .replace("uchar int vertex_indices", "uchar float vertex_indices")
+            .replace("4 0 1 2 3", "4 0 1 2 0.5");
+        assert!(Mesh::<f32>::read_ply(broken.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_attribute_length() {
+        let (mesh, mut attributes) = sphere_with_attributes();
+        attributes.scalars[1].1.pop();
+        let mut ply = Vec::new();
+        let result = mesh.write_ply_with(&mut ply, PlyFormat::Ascii, &attributes);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        attributes.scalars.clear();
+        attributes.colors.push((0, 0, 0));
+        let result = mesh.write_ply_with(&mut ply, PlyFormat::Ascii, &attributes);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+    }
+
+    #[test]
+    fn test_attribute_names() {
+        let (mesh, attributes) = sphere_with_attributes();
+        for &name in &["", "field value", "value\n", "x", "nz", "red"] {
+            let mut attributes = attributes.clone();
+            attributes.scalars[0].0 = name.to_string();
+            let mut ply = Vec::new();
+            let result = mesh.write_ply_with(&mut ply, PlyFormat::Ascii, &attributes);
+            assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        }
+    }
+
+    #[test]
+    fn test_index_too_large() {
+        let mesh = Mesh(
+            vec![Vertex(0.0, 0.0, 0.0); 3],
+            vec![Triangle(0, 1, usize::MAX)],
+            Vec::new(),
+        );
+        let mut ply = Vec::new();
+        let result = mesh.write_ply(&mut ply, PlyFormat::BinaryLittleEndian);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        assert!(ply.is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+
+use std::io;
+use std::io::Read;
+
+use crate::field::total_corner_count;
+use crate::field::Field;
+use crate::real::Real;
+
+// Type of the samples in a raw volume
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SampleType {
+    U8,
+    U16,
+    I16,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    pub fn size(self) -> usize {
+        match self {
+            SampleType::U8 => 1,
+            SampleType::U16 | SampleType::I16 => 2,
+            SampleType::F32 => 4,
+            SampleType::F64 => 8,
+        }
+    }
+}
+
+// Byte order of multi-byte samples
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl<T: Real> Field<T> {
+    // Reads a headerless volume of `dims` samples in the same x-major order as `Field::from_flat`.
+    // The reader must contain exactly the samples of the volume.
+    pub fn from_raw_reader<R: Read>(
+        reader: R,
+        dims: (usize, usize, usize),
+        sample_type: SampleType,
+        endianness: Endianness,
+    ) -> io::Result<Field<T>> {
+        Field::from_raw_reader_rescaled(reader, dims, sample_type, endianness, T::one(), T::zero())
+    }
+
+    // Same as `from_raw_reader`, but each sample `s` is mapped to `s * scale + offset`, e.g. to
+    // convert stored integers to physical units
+    pub fn from_raw_reader_rescaled<R: Read>(
+        reader: R,
+        dims: (usize, usize, usize),
+        sample_type: SampleType,
+        endianness: Endianness,
+        scale: T,
+        offset: T,
+    ) -> io::Result<Field<T>> {
+        let mut data = read_samples(reader, dims, sample_type, endianness)?;
+        if scale != T::one() || offset != T::zero() {
+            for v in &mut data {
+                *v = *v * scale + offset;
+            }
+        }
+        Ok(Field::try_from_flat(dims, data)?)
+    }
+}
+
+// Reads the samples of a volume of `dims` samples, which must be all that the reader contains.
+// Reading one byte more than needed is enough to tell that the reader is too long, so e.g. a
+// compressed stream inflating to far more data than the header says isn't read to the end, and
+// nothing is allocated up front for dimensions that don't match the data.
+pub fn read_samples<T: Real, R: Read>(
+    reader: R,
+    dims: (usize, usize, usize),
+    sample_type: SampleType,
+    endianness: Endianness,
+) -> io::Result<Vec<T>> {
+    let expected = sample_bytes(dims, sample_type)?;
+    let mut bytes = Vec::new();
+    reader
+        .take((expected as u64).saturating_add(1))
+        .read_to_end(&mut bytes)?;
+    decode_samples(&bytes, dims, sample_type, endianness)
+}
+
+// Converts raw bytes of a volume with `dims` samples into field values. Fails if the number of
+// bytes doesn't match the dimensions.
+pub fn decode_samples<T: Real>(
+    bytes: &[u8],
+    dims: (usize, usize, usize),
+    sample_type: SampleType,
+    endianness: Endianness,
+) -> io::Result<Vec<T>> {
+    let expected = sample_bytes(dims, sample_type)?;
+    if bytes.len() != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Volume of {:?} {:?} samples needs {} bytes, got {}",
+                dims,
+                sample_type,
+                expected,
+                bytes.len()
+            ),
+        ));
+    }
+
+    let size = sample_type.size();
+    let mut sample = [0u8; 8];
+    let samples = bytes.chunks(size).map(|chunk| {
+        sample[..size].copy_from_slice(chunk);
+        if endianness == Endianness::Big {
+            sample[..size].reverse();
+        }
+        match sample_type {
+            SampleType::U8 => f64::from(sample[0]),
+            SampleType::U16 => f64::from(u16::from_le_bytes([sample[0], sample[1]])),
+            SampleType::I16 => f64::from(i16::from_le_bytes([sample[0], sample[1]])),
+            SampleType::F32 => {
+                f64::from(f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            }
+            SampleType::F64 => f64::from_le_bytes(sample),
+        }
+    });
+    Ok(samples.map(T::from_f64).collect())
+}
+
+// Number of bytes in a volume of `dims` samples. Fails with `InvalidInput` if it overflows.
+fn sample_bytes(dims: (usize, usize, usize), sample_type: SampleType) -> io::Result<usize> {
+    total_corner_count(dims)
+        .and_then(|n| n.checked_mul(sample_type.size()))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Volume of {:?} {:?} samples is too large", dims, sample_type),
+            )
+        })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    // Directory unique to this test and process, emptied of anything left by a failed run
+    pub fn test_dir(name: &str) -> PathBuf {
+        let name = format!("marching_cubes_{}_{}", name, process::id());
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_sample_types() {
+        // First two samples of a 2x2x2 volume, padded with zeros
+        let read = |bytes: &[u8], sample_type: SampleType, endianness| {
+            let mut bytes = bytes.to_vec();
+            bytes.resize(8 * sample_type.size(), 0);
+            let field: Field<f64> =
+                Field::from_raw_reader(&bytes[..], (2, 2, 2), sample_type, endianness).unwrap();
+            field.as_slice()[..2].to_vec()
+        };
+        assert_eq!(
+            vec![7.0, 250.0],
+            read(&[7, 250], SampleType::U8, Endianness::Little)
+        );
+
+        let bytes = [0x01, 0x02, 0xff, 0x00];
+        assert_eq!(
+            vec![513.0, 255.0],
+            read(&bytes, SampleType::U16, Endianness::Little)
+        );
+        assert_eq!(
+            vec![258.0, 65280.0],
+            read(&bytes, SampleType::U16, Endianness::Big)
+        );
+        assert_eq!(
+            vec![258.0, -256.0],
+            read(&bytes, SampleType::I16, Endianness::Big)
+        );
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(-1.5f32).to_be_bytes());
+        bytes.extend_from_slice(&0.25f32.to_be_bytes());
+        assert_eq!(
+            vec![-1.5, 0.25],
+            read(&bytes, SampleType::F32, Endianness::Big)
+        );
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0.1f64.to_le_bytes());
+        bytes.extend_from_slice(&1e300f64.to_le_bytes());
+        assert_eq!(
+            vec![0.1, 1e300],
+            read(&bytes, SampleType::F64, Endianness::Little)
+        );
+    }
+
+    #[test]
+    fn test_rescale() {
+        let bytes: Vec<u8> = (0..8).collect();
+        let f: Field = Field::from_raw_reader_rescaled(
+            &bytes[..],
+            (2, 2, 2),
+            SampleType::U8,
+            Endianness::Little,
+            0.5,
+            -1.0,
+        )
+        .unwrap();
+        assert_eq!(f.f(0, 0, 0), -1.0);
+        assert_eq!(f.f(1, 1, 1), 2.5);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let bytes = [0u8; 15];
+        let result: io::Result<Field> =
+            Field::from_raw_reader(&bytes[..], (2, 2, 2), SampleType::U16, Endianness::Little);
+        assert_eq!(io::ErrorKind::InvalidData, result.err().unwrap().kind());
+        let result: io::Result<Field> =
+            Field::from_raw_reader(&bytes[..], (2, 2, 2), SampleType::U8, Endianness::Little);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_too_large() {
+        // Dimensions far larger than the data fail without allocating for them
+        let bytes = [0u8; 16];
+        let dims = (1 << 20, 1 << 20, 1 << 10);
+        let result: io::Result<Field> =
+            Field::from_raw_reader(&bytes[..], dims, SampleType::U8, Endianness::Little);
+        assert_eq!(io::ErrorKind::InvalidData, result.err().unwrap().kind());
+
+        let dims = (usize::MAX, 2, 2);
+        let result: io::Result<Field> =
+            Field::from_raw_reader(&bytes[..], dims, SampleType::U16, Endianness::Little);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        let result = decode_samples::<f32>(&bytes, dims, SampleType::U8, Endianness::Little);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let mut bytes = Vec::new();
+        for &v in &[0.0f32, 1.0, f32::NAN, 3.0, 4.0, 5.0, 6.0, 7.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let result: io::Result<Field> =
+            Field::from_raw_reader(&bytes[..], (2, 2, 2), SampleType::F32, Endianness::Little);
+        assert_eq!(io::ErrorKind::InvalidData, result.err().unwrap().kind());
+    }
+}
@@ -0,0 +1,87 @@
+
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Div;
+use std::ops::Mul;
+use std::ops::Neg;
+use std::ops::Sub;
+use std::str::FromStr;
+
+// Floating point type used for field values and vertex positions. Implemented for `f32` and `f64`.
+pub trait Real:
+    Copy
+    + Debug
+    + Display
+    + FromStr
+    + Default
+    + PartialOrd
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+{
+    fn from_f64(v: f64) -> Self;
+    fn from_usize(v: usize) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn cos(self) -> Self;
+    fn acos(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn is_nan(self) -> bool;
+
+    fn zero() -> Self {
+        Self::from_f64(0.0)
+    }
+    fn one() -> Self {
+        Self::from_f64(1.0)
+    }
+}
+
+macro_rules! impl_real {
+    ($t:ident) => {
+        impl Real for $t {
+            fn from_f64(v: f64) -> $t {
+                v as $t
+            }
+            fn from_usize(v: usize) -> $t {
+                v as $t
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn sqrt(self) -> $t {
+                $t::sqrt(self)
+            }
+            fn abs(self) -> $t {
+                $t::abs(self)
+            }
+            fn cos(self) -> $t {
+                $t::cos(self)
+            }
+            fn acos(self) -> $t {
+                $t::acos(self)
+            }
+            fn min(self, other: $t) -> $t {
+                $t::min(self, other)
+            }
+            fn max(self, other: $t) -> $t {
+                $t::max(self, other)
+            }
+            fn is_nan(self) -> bool {
+                $t::is_nan(self)
+            }
+        }
+    };
+}
+
+impl_real!(f32);
+impl_real!(f64);
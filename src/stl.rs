@@ -0,0 +1,157 @@
+
+use std::io;
+use std::io::Write;
+
+use crate::mesh::Mesh;
+use crate::real::Real;
+
+// Result of validating a mesh written as STL. Slicers expect a closed surface, so `open_edges`
+// should be checked before printing. Surfaces cut by the boundary of the field are open.
+#[derive(Clone, Debug)]
+pub struct StlReport {
+    pub triangle_count: usize,
+    // Edges not joining exactly two consistently wound triangles, see `Mesh::open_edges`
+    pub open_edges: Vec<(usize, usize)>,
+}
+
+impl StlReport {
+    pub fn is_closed(&self) -> bool {
+        self.open_edges.is_empty()
+    }
+}
+
+impl<T: Real> Mesh<T> {
+    // Writes the mesh as binary STL. Facet normals are computed from the triangle winding, and
+    // coordinates are stored as `f32`. Fails with `InvalidInput` if the triangle count doesn't fit
+    // in the `u32` of the header.
+    pub fn write_stl<W: Write>(&self, w: &mut W) -> io::Result<StlReport> {
+        let triangle_count = u32::try_from(self.1.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many triangles for binary STL",
+            )
+        })?;
+        let mut header = [0u8; 80];
+        let title = b"binary STL written by marching-cubes";
+        header[..title.len()].copy_from_slice(title);
+        w.write_all(&header)?;
+        w.write_all(&triangle_count.to_le_bytes())?;
+
+        for (t, n) in self.1.iter().zip(self.face_normals()) {
+            let mut facet = Vec::with_capacity(50);
+            let coords = [
+                (n.0, n.1, n.2),
+                (self.0[t.0].0, self.0[t.0].1, self.0[t.0].2),
+                (self.0[t.1].0, self.0[t.1].1, self.0[t.1].2),
+                (self.0[t.2].0, self.0[t.2].1, self.0[t.2].2),
+            ];
+            for &(x, y, z) in &coords {
+                for &c in &[x, y, z] {
+                    facet.extend_from_slice(&(c.to_f64() as f32).to_le_bytes());
+                }
+            }
+            // Attribute byte count
+            facet.extend_from_slice(&[0, 0]);
+            w.write_all(&facet)?;
+        }
+        Ok(self.stl_report())
+    }
+
+    // Writes the mesh as ASCII STL with the given solid name
+    pub fn write_stl_ascii<W: Write>(&self, w: &mut W, name: &str) -> io::Result<StlReport> {
+        writeln!(w, "solid {}", name)?;
+        for (t, n) in self.1.iter().zip(self.face_normals()) {
+            writeln!(w, "  facet normal {} {} {}", n.0, n.1, n.2)?;
+            writeln!(w, "    outer loop")?;
+            for &i in &[t.0, t.1, t.2] {
+                let v = &self.0[i];
+                writeln!(w, "      vertex {} {} {}", v.0, v.1, v.2)?;
+            }
+            writeln!(w, "    endloop")?;
+            writeln!(w, "  endfacet")?;
+        }
+        writeln!(w, "endsolid {}", name)?;
+        Ok(self.stl_report())
+    }
+
+    fn stl_report(&self) -> StlReport {
+        StlReport {
+            triangle_count: self.1.len(),
+            open_edges: self.open_edges(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tessellator::tests::sphere_mesh;
+
+    fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(&bytes[offset..(offset + 4)]);
+        f32::from_le_bytes(b)
+    }
+
+    #[test]
+    fn test_binary() {
+        let mesh = sphere_mesh(0.98);
+        let mut stl = Vec::new();
+        let report = mesh.write_stl(&mut stl).unwrap();
+        assert!(report.is_closed());
+        assert_eq!(mesh.1.len(), report.triangle_count);
+        assert_eq!(84 + 50 * mesh.1.len(), stl.len());
+        assert_eq!(
+            mesh.1.len() as u32,
+            u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]])
+        );
+
+        for (i, t) in mesh.1.iter().enumerate() {
+            let facet = &stl[(84 + 50 * i)..(84 + 50 * (i + 1))];
+            let v0 = &mesh.0[t.0];
+            assert_eq!(v0.0, read_f32(facet, 12));
+            assert_eq!(v0.1, read_f32(facet, 16));
+            assert_eq!(v0.2, read_f32(facet, 20));
+            assert_eq!(mesh.0[t.2].2, read_f32(facet, 44));
+
+            // Normals point outwards
+            let n = (read_f32(facet, 0), read_f32(facet, 4), read_f32(facet, 8));
+            assert!(n.0 * v0.0 + n.1 * v0.1 + n.2 * v0.2 > 0.0);
+            assert!((n.0 * n.0 + n.1 * n.1 + n.2 * n.2 - 1.0).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mesh = sphere_mesh(0.98);
+        let mut stl = Vec::new();
+        let report = mesh.write_stl_ascii(&mut stl, "sphere").unwrap();
+        assert!(report.is_closed());
+
+        let stl = String::from_utf8(stl).unwrap();
+        assert!(stl.starts_with("solid sphere\n"));
+        assert!(stl.ends_with("endsolid sphere\n"));
+        assert_eq!(mesh.1.len(), stl.matches("facet normal").count());
+        assert_eq!(3 * mesh.1.len(), stl.matches("vertex").count());
+
+        let first_vertex: Vec<f32> = stl
+            .lines()
+            .find(|l| l.trim_start().starts_with("vertex"))
+            .unwrap()
+            .split_whitespace()
+            .skip(1)
+            .map(|c| c.parse().unwrap())
+            .collect();
+        let v = &mesh.0[mesh.1[0].0];
+        assert_eq!(vec![v.0, v.1, v.2], first_vertex);
+    }
+
+    #[test]
+    fn test_open() {
+        // Sphere is cut by the boundary of the field
+        let mesh = sphere_mesh(1.2);
+        let report = mesh.write_stl(&mut io::sink()).unwrap();
+        assert!(!report.is_closed());
+    }
+}
@@ -0,0 +1,342 @@
+
+use crate::cube::corners_inside;
+use crate::cube::crossed_edges;
+use crate::cube::edge_intersection;
+use crate::cube::edge_normal;
+use crate::cube::normalize;
+use crate::error::Error;
+use crate::field::ScalarField;
+use crate::mesh::Mesh;
+use crate::mesh::Normal;
+use crate::mesh::Triangle;
+use crate::mesh::Vertex;
+use crate::real::Real;
+use crate::tessellator::cube_gradients;
+use crate::tessellator::cube_positions;
+use crate::tessellator::cube_size;
+use crate::tessellator::cube_values;
+use crate::tessellator::determinant;
+use crate::tessellator::validate;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Quad(pub usize, pub usize, pub usize, pub usize);
+
+// Vertices, quads and per-vertex normals, like `Mesh` but with quads wound counter-clockwise seen
+// from outside the volume
+pub struct QuadMesh<T = f32>(pub Vec<Vertex<T>>, pub Vec<Quad>, pub Vec<Normal<T>>);
+
+impl<T: Real> QuadMesh<T> {
+    // Splits each quad into two triangles along its shorter diagonal
+    pub fn triangulate(&self) -> Mesh<T> {
+        let distance = |i: usize, j: usize| {
+            let (a, b) = (&self.0[i], &self.0[j]);
+            (a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1) + (a.2 - b.2) * (a.2 - b.2)
+        };
+        let mut tris = Vec::with_capacity(2 * self.1.len());
+        for &Quad(a, b, c, d) in &self.1 {
+            if distance(a, c) <= distance(b, d) {
+                tris.push(Triangle(a, b, c));
+                tris.push(Triangle(a, c, d));
+            } else {
+                tris.push(Triangle(a, b, d));
+                tris.push(Triangle(b, c, d));
+            }
+        }
+        Mesh(self.0.clone(), tris, self.2.clone())
+    }
+}
+
+const NO_VERTEX: usize = usize::MAX;
+
+// Creates a naive surface net of the field: one vertex in each cube where the field crosses the
+// iso-value, at the mean of the edge intersections of the cube, and a quad joining the vertices
+// of the four cubes around each grid edge where the field crosses the iso-value. Compared to
+// `create_mesh`, the result has fewer and more evenly shaped faces, but sharp features are
+// rounded off. Vertex normals are computed from the field gradient when `normals` is set. Fails
+// like `create_mesh`.
+pub fn create_surface_net<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    iso_value: T,
+    normals: bool,
+) -> Result<QuadMesh<T>, Error> {
+    create_dual_mesh(field, min_bound, max_bound, iso_value, |cell| {
+        let mut sum = (T::zero(), T::zero(), T::zero());
+        let mut n = 0;
+        for i in 0..12 {
+            if let Some(v) = edge_intersection(cell.edges, i, &cell.p, &cell.f, iso_value) {
+                sum = (sum.0 + v.0, sum.1 + v.1, sum.2 + v.2);
+                n += 1;
+            }
+        }
+        let n = T::from_usize(n);
+        let vertex = Vertex(sum.0 / n, sum.1 / n, sum.2 / n);
+
+        let normal = if normals {
+            let g = cube_gradients(field, &cell.p, cell.x, cell.y, cell.z);
+            let mut sum = (T::zero(), T::zero(), T::zero());
+            for i in (0..12).filter(|&i| (cell.edges >> i) & 1 != 0) {
+                let n = edge_normal(i, &g, &cell.f, iso_value);
+                sum = (sum.0 + n.0, sum.1 + n.1, sum.2 + n.2);
+            }
+            Some(normalize(sum))
+        } else {
+            None
+        };
+        (vertex, normal)
+    })
+}
+
+// Cube where the field crosses the iso-value, with corner positions `p` and values `f` in the order
+// of `CORNERS`, and the crossed edges as given by `crossed_edges`
+pub struct DualCell<T> {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub p: [(T, T, T); 8],
+    pub f: [T; 8],
+    pub edges: usize,
+}
+
+// Creates a quad mesh with the connectivity of a surface net, with the vertex and optional normal
+// of each cell placed by `place`. `place` has to return a normal either for every cell or for
+// none.
+pub fn create_dual_mesh<T, F, P>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    iso_value: T,
+    mut place: P,
+) -> Result<QuadMesh<T>, Error>
+where
+    T: Real,
+    F: ScalarField<T> + ?Sized,
+    P: FnMut(&DualCell<T>) -> (Vertex<T>, Option<Normal<T>>),
+{
+    validate(field, min_bound, max_bound)?;
+    let cube_count = field.cube_count();
+    let cube_size = cube_size(min_bound, max_bound, &cube_count);
+    let inside = |x: usize, y: usize, z: usize| field.f(x, y, z) < iso_value;
+
+    let mut verts = Vec::new();
+    let mut quads = Vec::new();
+    let mut normals = Vec::new();
+    let mut push_quad = |q: [usize; 4], flip: bool| {
+        if flip {
+            quads.push(Quad(q[3], q[2], q[1], q[0]));
+        } else {
+            quads.push(Quad(q[0], q[1], q[2], q[3]));
+        }
+    };
+
+    // Vertices of the cubes of the previous and current slab, indexed by `x + y * cube_count.0`
+    let index = |x: usize, y: usize| x + y * cube_count.0;
+    let layer_len = cube_count.0 * cube_count.1;
+    let mut layers = [vec![NO_VERTEX; layer_len], vec![NO_VERTEX; layer_len]];
+    // Whether each cube of the current slab is placed with a reflection, which flips the winding of
+    // the quads. Every quad is next to cube (x, y, z) of the grid edge it crosses, and is wound by
+    // that cube like the triangles of `create_mesh`.
+    let mut mirrored = vec![false; layer_len];
+    for z in 0..cube_count.2 {
+        layers.swap(0, 1);
+        layers[1].fill(NO_VERTEX);
+        for y in 0..cube_count.1 {
+            for x in 0..cube_count.0 {
+                let f = cube_values(field, x, y, z);
+                let edges = crossed_edges(corners_inside(&f, iso_value));
+                if edges == 0 {
+                    continue;
+                }
+                let cell = DualCell {
+                    x,
+                    y,
+                    z,
+                    p: cube_positions(field, min_bound, &cube_size, x, y, z),
+                    f,
+                    edges,
+                };
+                let (vertex, normal) = place(&cell);
+                mirrored[index(x, y)] = determinant(&cell.p) < T::zero();
+                layers[1][index(x, y)] = verts.len();
+                verts.push(vertex);
+                normals.extend(normal);
+            }
+        }
+
+        // Quads around z-directed edges between corner layers `z` and `z + 1`, counter-clockwise
+        // seen from +z when the lower corner is inside
+        let (prev, cur) = (&layers[0], &layers[1]);
+        for y in 1..cube_count.1 {
+            for x in 1..cube_count.0 {
+                let (a, b) = (inside(x, y, z), inside(x, y, z + 1));
+                if a != b {
+                    let q = [
+                        cur[index(x - 1, y - 1)],
+                        cur[index(x, y - 1)],
+                        cur[index(x, y)],
+                        cur[index(x - 1, y)],
+                    ];
+                    push_quad(q, b != mirrored[index(x, y)]);
+                }
+            }
+        }
+        if z == 0 {
+            continue;
+        }
+
+        // Quads around x- and y-directed edges on corner layer `z`
+        for y in 1..cube_count.1 {
+            for x in 0..cube_count.0 {
+                let (a, b) = (inside(x, y, z), inside(x + 1, y, z));
+                if a != b {
+                    let q = [
+                        prev[index(x, y - 1)],
+                        prev[index(x, y)],
+                        cur[index(x, y)],
+                        cur[index(x, y - 1)],
+                    ];
+                    push_quad(q, b != mirrored[index(x, y)]);
+                }
+            }
+        }
+        for y in 0..cube_count.1 {
+            for x in 1..cube_count.0 {
+                let (a, b) = (inside(x, y, z), inside(x, y + 1, z));
+                if a != b {
+                    let q = [
+                        prev[index(x - 1, y)],
+                        cur[index(x - 1, y)],
+                        cur[index(x, y)],
+                        prev[index(x, y)],
+                    ];
+                    push_quad(q, b != mirrored[index(x, y)]);
+                }
+            }
+        }
+    }
+    Ok(QuadMesh(verts, quads, normals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Bounds;
+    use crate::field::Field;
+    use crate::tessellator::create_mesh;
+
+    fn sphere() -> Field {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        Field::from_closure(
+            |x, y, z| (x * x + y * y + z * z).sqrt() - 0.8,
+            &min,
+            &max,
+            &(30, 31, 32),
+        )
+    }
+
+    #[test]
+    fn test_plane() {
+        // Mean of the intersections of an axis-aligned plane with a cube is on the plane
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let field = Field::from_closure(|x, _, _| x - 0.55, &min, &max, &(4, 4, 4));
+        let net = create_surface_net(&field, &min, &max, 0.0, false).unwrap();
+        assert_eq!(16, net.0.len());
+        assert_eq!(9, net.1.len());
+        assert!(net.0.iter().all(|v| (v.0 - 0.55).abs() < 1e-6));
+        let mesh = net.triangulate();
+        assert_eq!(18, mesh.1.len());
+        assert!(mesh.face_normals().iter().all(|n| n.0 > 0.999));
+    }
+
+    #[test]
+    fn test_sphere() {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let net = create_surface_net(&sphere(), &min, &max, 0.0, true).unwrap();
+        assert_eq!(net.0.len(), net.2.len());
+        let mesh = net.triangulate();
+        assert!(mesh.is_closed());
+
+        // About one quad for every two triangles of marching cubes, close to the sphere and facing
+        // outwards
+        let classic = create_mesh(&sphere(), &min, &max).unwrap();
+        assert!(net.1.len() * 100 < classic.1.len() * 51);
+        for (v, n) in mesh.0.iter().zip(&mesh.2) {
+            let r = (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+            assert!((r - 0.8).abs() < 0.01);
+            assert!((v.0 * n.0 + v.1 * n.1 + v.2 * n.2) / r > 0.99);
+        }
+        for (t, n) in mesh.1.iter().zip(mesh.face_normals()) {
+            let v = &mesh.0[t.0];
+            assert!(v.0 * n.0 + v.1 * n.1 + v.2 * n.2 > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_mirrored() {
+        // Grid flipped along x still gives outward facing quads
+        let bounds = Bounds {
+            min: (1.0, -1.0, -1.0),
+            max: (-1.0, 1.0, 1.0),
+        };
+        let field = sphere().with_bounds(&bounds);
+        let net = create_surface_net(&field, &bounds.min, &bounds.max, 0.0, false);
+        let mesh = net.unwrap().triangulate();
+        assert!(mesh.is_closed());
+        for (t, n) in mesh.1.iter().zip(mesh.face_normals()) {
+            let v = &mesh.0[t.0];
+            assert!(v.0 * n.0 + v.1 * n.1 + v.2 * n.2 > 0.0);
+        }
+    }
+
+    // Sphere around grid corner (10, 10, 10), with the grid mirrored along x from corner layer
+    // z = 10 on, so that cubes are placed with and without a reflection
+    struct SplitSphere;
+
+    impl ScalarField<f64> for SplitSphere {
+        fn corner_count(&self) -> (usize, usize, usize) {
+            (21, 21, 21)
+        }
+        fn f(&self, x: usize, y: usize, z: usize) -> f64 {
+            let d = |c: usize| c as f64 - 10.0;
+            (d(x) * d(x) + d(y) * d(y) + d(z) * d(z)).sqrt() - 7.0
+        }
+        fn position(&self, x: usize, y: usize, z: usize) -> Option<(f64, f64, f64)> {
+            let x = if z < 10 { x as f64 } else { -(x as f64) };
+            Some((x, y as f64, z as f64))
+        }
+    }
+
+    #[test]
+    fn test_mirrored_part() {
+        // Quads away from where the reflection starts face away from the center of their half
+        let net = create_surface_net(&SplitSphere, &(0.0, 0.0, 0.0), &(1.0, 1.0, 1.0), 0.0, false);
+        let net = net.unwrap();
+        let mut checked = (0, 0);
+        for &Quad(a, b, c, d) in &net.1 {
+            let v: Vec<&Vertex<f64>> = [a, b, c, d].iter().map(|&i| &net.0[i]).collect();
+            let center = if v.iter().all(|v| v.2 < 9.0) {
+                checked.0 += 1;
+                (10.0, 10.0, 10.0)
+            } else if v.iter().all(|v| v.2 > 11.0) {
+                checked.1 += 1;
+                (-10.0, 10.0, 10.0)
+            } else {
+                continue;
+            };
+            let (e0, e1) = (
+                (v[2].0 - v[0].0, v[2].1 - v[0].1, v[2].2 - v[0].2),
+                (v[3].0 - v[1].0, v[3].1 - v[1].1, v[3].2 - v[1].2),
+            );
+            let n = (
+                e0.1 * e1.2 - e0.2 * e1.1,
+                e0.2 * e1.0 - e0.0 * e1.2,
+                e0.0 * e1.1 - e0.1 * e1.0,
+            );
+            let r = (v[0].0 - center.0, v[0].1 - center.1, v[0].2 - center.2);
+            assert!(n.0 * r.0 + n.1 * r.1 + n.2 * r.2 > 0.0);
+        }
+        assert!(checked.0 > 100 && checked.1 > 100);
+    }
+}
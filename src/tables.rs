@@ -1,4 +1,16 @@
 
+// Map cube's vertex index to its offset in the grid
+pub const CORNERS: [(usize, usize, usize); 8] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (1, 0, 1),
+    (0, 0, 1),
+    (0, 1, 0),
+    (1, 1, 0),
+    (1, 1, 1),
+    (0, 1, 1),
+];
+
 // Map cube's edge index to vertex index pair
 pub const EDGES: [(usize, usize); 12] = [
     (0, 1),
@@ -15,6 +27,64 @@ pub const EDGES: [(usize, usize); 12] = [
     (3, 7),
 ];
 
+// Map cube's face and body diagonal index to vertex index pair. Diagonals of opposite faces are
+// parallel, so adjacent cubes use the same diagonal on their shared face.
+pub const DIAGONALS: [(usize, usize); 7] = [
+    (0, 5),
+    (3, 6),
+    (0, 2),
+    (4, 6),
+    (0, 7),
+    (1, 6),
+    (0, 6),
+];
+
+// Split of a cube into 6 tetrahedra around the body diagonal from vertex 0 to 6, as vertex indices
+// of the cube. Vertices of each tetrahedron are in positive order, i.e. the second, third and
+// fourth vertex are counter-clockwise seen from the first one.
+pub const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 5, 6],
+    [0, 1, 6, 2],
+    [0, 4, 6, 5],
+    [0, 4, 7, 6],
+    [0, 3, 2, 6],
+    [0, 3, 6, 7],
+];
+
+// Map tetrahedron's edge index to vertex index pair of the tetrahedron
+pub const TETRAHEDRON_EDGES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+// Map edges of each tetrahedron in `TETRAHEDRA` to cube edges, with diagonals in `DIAGONALS`
+// following the 12 edges from index 16 onwards
+pub const TETRAHEDRA_TO_CUBE_EDGES: [[usize; 6]; 6] = [
+    [0, 16, 22, 9, 21, 5],
+    [0, 22, 18, 21, 1, 10],
+    [8, 22, 16, 19, 4, 5],
+    [8, 20, 22, 7, 19, 6],
+    [3, 18, 22, 2, 17, 10],
+    [3, 22, 20, 17, 11, 6],
+];
+
+// Map vertices of a tetrahedron inside volume (bitmap) to triangles of tetrahedron edges
+pub const TETRAHEDRON_VERTS_INSIDE_TO_TRIS: [[Option<(usize, usize, usize)>; 2]; 16] = [
+    [None, None],
+    [Some((0, 1, 2)), None],
+    [Some((0, 4, 3)), None],
+    [Some((1, 2, 4)), Some((1, 4, 3))],
+    [Some((1, 3, 5)), None],
+    [Some((0, 5, 2)), Some((0, 3, 5))],
+    [Some((0, 5, 1)), Some((0, 4, 5))],
+    [Some((2, 4, 5)), None],
+    [Some((2, 5, 4)), None],
+    [Some((0, 1, 5)), Some((0, 5, 4))],
+    [Some((0, 2, 5)), Some((0, 5, 3))],
+    [Some((1, 5, 3)), None],
+    [Some((1, 4, 2)), Some((1, 3, 4))],
+    [Some((0, 3, 4)), None],
+    [Some((0, 2, 1)), None],
+    [None, None],
+];
+
 // Map vertices inside volume (bitmap) to bitmap of edges that intersect the iso-surface
 pub const VERTS_INSIDE_TO_EDGE_ISECT: [usize; 256] = [
     0x0,
@@ -276,7 +346,7 @@ pub const VERTS_INSIDE_TO_EDGE_ISECT: [usize; 256] = [
 ];
 
 // Map edge intersection bitmap to triangle tessellation, where index points to an edge index.
-pub const EDGE_ISECTS_TO_TRIS: [[Option<(usize, usize, usize)>; 5]; 256] =
+pub static EDGE_ISECTS_TO_TRIS: [[Option<(usize, usize, usize)>; 5]; 256] =
     [
         [None, None, None, None, None],
         [Some((0, 8, 3)), None, None, None, None],
@@ -1,94 +1,420 @@
 
-use mesh::Mesh;
-use mesh::Triangle;
-
-use std::collections::HashMap;
-use cube::tessellate_corners;
-use field::Field;
-
-pub fn create_mesh(
-    field: &Field,
-    min_bound: &(f32, f32, f32),
-    max_bound: &(f32, f32, f32),
-) -> Mesh {
-    let cube_size = (
-        (max_bound.0 - min_bound.0) / (field.cube_count().0 as f32),
-        (max_bound.1 - min_bound.1) / (field.cube_count().1 as f32),
-        (max_bound.2 - min_bound.2) / (field.cube_count().2 as f32),
-    );
-    let mut verts = Vec::new();
-    let mut tris = Vec::new();
-
-    let mut edge_to_vert_map = HashMap::new();
+use crate::mesh::Mesh;
+use crate::mesh::Triangle;
+use crate::mesh::Vertex;
+use crate::mesh::Normal;
 
+use crate::contour::INNER_VERTEX;
+use crate::cube::tessellate_corners_into;
+use crate::cube::edge_normal;
+use crate::cube::center_normal;
+use crate::cube::segment_normal;
+use crate::cube::CUBE_TRI_COUNT;
+use crate::cube::CUBE_VERT_COUNT;
+use crate::error::Error;
+use crate::field::validate_bounds;
+use crate::field::ScalarField;
+use crate::mc33::tessellate_corners_mc33_into;
+use crate::real::Real;
+use crate::tables::CORNERS;
+use crate::tetrahedra::segment;
+use crate::tetrahedra::tessellate_tetrahedra_into;
+use crate::tetrahedra::DIAGONAL_VERTEX;
+
+// Grid of the field is spread evenly between `min_bound` and `max_bound`, unless the field defines
+// positions of its corners with `ScalarField::position`. Fails if the field has less than two
+// corners along some axis, if used bounds aren't increasing along every axis, or if the field
+// places its grid degenerately, e.g. with zero spacing.
+pub fn create_mesh<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+) -> Result<Mesh<T>, Error> {
+    create_mesh_with(field, min_bound, max_bound, &MeshOptions::default())
+}
+
+pub fn create_mesh_iso<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    iso_value: T,
+) -> Result<Mesh<T>, Error> {
+    let options = MeshOptions {
+        iso_value,
+        ..MeshOptions::default()
+    };
+    create_mesh_with(field, min_bound, max_bound, &options)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshOptions<T = f32> {
+    // Corners with field value below `iso_value` are considered to be inside the volume.
+    pub iso_value: T,
+    // Compute per-vertex normals from the central difference gradient of the field.
+    pub normals: bool,
+    // How each cube of the grid is split into triangles.
+    pub tessellation: Tessellation,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Tessellation {
+    // Classic case table of Lorensen and Cline as given by Paul Bourke, with ambiguous faces
+    // resolved by the asymptotic decider so that output is crack-free. Tunnels through cubes
+    // aren't detected.
+    #[default]
+    Classic,
+    // Chernyaev's Marching Cubes 33, which follows the topology of the trilinear interpolant of
+    // the field. Tunnels through cubes are preserved as well.
+    Mc33,
+    // Marching tetrahedra over 6 tetrahedra per cube. Free of ambiguities, but produces about
+    // twice as many triangles as the cube tables.
+    Tetrahedra,
+}
+
+pub fn create_mesh_with<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    options: &MeshOptions<T>,
+) -> Result<Mesh<T>, Error> {
+    validate(field, min_bound, max_bound)?;
+    let mut builder = MeshBuilder::new(options, min_bound, max_bound, &field.cube_count());
     for z in 0..field.cube_count().2 {
+        builder.march_slab(field, z);
+    }
+    Ok(builder.finish())
+}
+
+// Checks that the field has cubes to tessellate and that the bounds are valid or, if the field
+// places its corners itself, that its first cube has volume
+pub fn validate<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+) -> Result<(), Error> {
+    let c = field.corner_count();
+    if c.0 < 2 || c.1 < 2 || c.2 < 2 {
+        return Err(Error::TooFewCorners { corner_count: c });
+    }
+    match field.position(0, 0, 0) {
+        None => validate_bounds(min_bound, max_bound)?,
+        Some(p0) => {
+            // Fields that only place some of their corners are rejected like degenerate ones
+            let position = |x, y, z| field.position(x, y, z).ok_or(Error::DegenerateGrid);
+            let p = [
+                p0,
+                position(1, 0, 0)?,
+                p0,
+                position(0, 0, 1)?,
+                position(0, 1, 0)?,
+            ];
+            let det = determinant(&p);
+            if det == T::zero() || det.is_nan() {
+                return Err(Error::DegenerateGrid);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Accumulates the mesh one slab of cubes at a time, welding vertices on edges shared between
+// cubes. Slabs have to be marched in increasing z order.
+pub struct MeshBuilder<'a, T: 'a> {
+    options: &'a MeshOptions<T>,
+    min_bound: (T, T, T),
+    cube_size: (T, T, T),
+    verts: Vec<Vertex<T>>,
+    tris: Vec<Triangle>,
+    normals: Vec<Normal<T>>,
+    edge_cache: EdgeCache,
+}
+
+impl<'a, T: Real> MeshBuilder<'a, T> {
+    pub fn new(
+        options: &'a MeshOptions<T>,
+        min_bound: &(T, T, T),
+        max_bound: &(T, T, T),
+        cube_count: &(usize, usize, usize),
+    ) -> MeshBuilder<'a, T> {
+        MeshBuilder {
+            options,
+            min_bound: *min_bound,
+            cube_size: cube_size(min_bound, max_bound, cube_count),
+            verts: Vec::new(),
+            tris: Vec::new(),
+            normals: Vec::new(),
+            edge_cache: EdgeCache::new(
+                cube_count.0 + 1,
+                cube_count.1 + 1,
+                options.tessellation == Tessellation::Tetrahedra,
+            ),
+        }
+    }
+
+    // Tessellates cubes between corner layers `z` and `z + 1`. Field is only sampled at those
+    // layers, or also at the neighbouring ones if normals are computed.
+    pub fn march_slab<F: ScalarField<T> + ?Sized>(&mut self, field: &F, z: usize) {
+        let (min_bound, cube_size, iso) = (self.min_bound, self.cube_size, self.options.iso_value);
+        self.edge_cache.start_slab(z);
+        let zero = (T::zero(), T::zero(), T::zero());
+        let mut cube_verts = [zero; CUBE_VERT_COUNT];
+        let mut cube_tris = [(0, 0, 0); CUBE_TRI_COUNT];
+
         for y in 0..field.cube_count().1 {
             for x in 0..field.cube_count().0 {
-                let (fx, fy, fz) = (x as f32, y as f32, z as f32);
-                let c0 = (
-                    min_bound.0 + fx * cube_size.0,
-                    min_bound.1 + fy * cube_size.1,
-                    min_bound.2 + fz * cube_size.2,
-                );
-                let c1 = (c0.0 + cube_size.0, c0.1 + cube_size.1, c0.2 + cube_size.2);
-                let p = [
-                    (c0.0, c0.1, c0.2),
-                    (c1.0, c0.1, c0.2),
-                    (c1.0, c0.1, c1.2),
-                    (c0.0, c0.1, c1.2),
-                    (c0.0, c1.1, c0.2),
-                    (c1.0, c1.1, c0.2),
-                    (c1.0, c1.1, c1.2),
-                    (c0.0, c1.1, c1.2),
-                ];
-                let f = [
-                    field.f(x, y, z),
-                    field.f(x + 1, y, z),
-                    field.f(x + 1, y, z + 1),
-                    field.f(x, y, z + 1),
-                    field.f(x, y + 1, z),
-                    field.f(x + 1, y + 1, z),
-                    field.f(x + 1, y + 1, z + 1),
-                    field.f(x, y + 1, z + 1),
-                ];
-                let Mesh(cube_verts, cube_tris) = tessellate_corners(&p, &f);
-                for Triangle(i0, i1, i2) in cube_tris {
-                    let (e0, e1, e2) = (
-                        grid_shared_edge_index(x, y, z, i0),
-                        grid_shared_edge_index(x, y, z, i1),
-                        grid_shared_edge_index(x, y, z, i2),
-                    );
-                    let v0 = if let Some(v) = edge_to_vert_map.get(&e0) {
-                        *v
-                    } else {
-                        let v = verts.len();
-                        edge_to_vert_map.insert(e0, v);
-                        verts.push(cube_verts[i0].clone());
-                        v
-                    };
-                    let v1 = if let Some(v) = edge_to_vert_map.get(&e1) {
-                        *v
-                    } else {
-                        let v = verts.len();
-                        edge_to_vert_map.insert(e1, v);
-                        verts.push(cube_verts[i1].clone());
-                        v
-                    };
-                    let v2 = if let Some(v) = edge_to_vert_map.get(&e2) {
-                        *v
+                let p = cube_positions(field, &min_bound, &cube_size, x, y, z);
+                let f = cube_values(field, x, y, z);
+                let tri_count = match self.options.tessellation {
+                    Tessellation::Classic => {
+                        tessellate_corners_into(&p, &f, iso, &mut cube_verts, &mut cube_tris)
+                    }
+                    Tessellation::Mc33 => {
+                        tessellate_corners_mc33_into(&p, &f, iso, &mut cube_verts, &mut cube_tris)
+                    }
+                    Tessellation::Tetrahedra => {
+                        tessellate_tetrahedra_into(&p, &f, iso, &mut cube_verts, &mut cube_tris)
+                    }
+                };
+                if tri_count == 0 {
+                    continue;
+                }
+                // Grid placed with a reflection flips the winding of the triangles
+                let mirrored = determinant(&p) < T::zero();
+                let g = if self.options.normals {
+                    cube_gradients(field, &p, x, y, z)
+                } else {
+                    [zero; 8]
+                };
+                let normal = |i: usize| {
+                    if i < INNER_VERTEX {
+                        edge_normal(i, &g, &f, iso)
+                    } else if i < DIAGONAL_VERTEX {
+                        center_normal(&g)
                     } else {
-                        let v = verts.len();
-                        edge_to_vert_map.insert(e2, v);
-                        verts.push(cube_verts[i2].clone());
-                        v
-                    };
+                        segment_normal(segment(i), &g, &f, iso)
+                    }
+                };
+                // Vertices inside the cube aren't shared with other cubes
+                let mut unshared = [NO_VERTEX; CUBE_VERT_COUNT];
+                for &(i0, i1, i2) in &cube_tris[..tri_count] {
+                    let mut v = [0; 3];
+                    for (v, &i) in v.iter_mut().zip(&[i0, i1, i2]) {
+                        let slot = match grid_shared_vertex_index(x, y, z, i) {
+                            Some(edge) => self.edge_cache.slot(edge),
+                            None => &mut unshared[i],
+                        };
+                        if *slot == NO_VERTEX {
+                            *slot = self.verts.len();
+                            let (vx, vy, vz) = cube_verts[i];
+                            self.verts.push(Vertex(vx, vy, vz));
+                            if self.options.normals {
+                                self.normals.push(normal(i));
+                            }
+                        }
+                        *v = *slot;
+                    }
 
-                    tris.push(Triangle(v0, v1, v2));
+                    if mirrored {
+                        self.tris.push(Triangle(v[0], v[2], v[1]));
+                    } else {
+                        self.tris.push(Triangle(v[0], v[1], v[2]));
+                    }
                 }
             }
         }
     }
-    Mesh(verts, tris)
+
+    // Vertices welded to x- and y-directed edges on corner layer `z`, indexed as in `EdgeCache`.
+    // Only the layers of the latest marched slab are available.
+    #[cfg(feature = "parallel")]
+    pub fn layer_vertices(&self, z: usize) -> Vec<Option<usize>> {
+        self.edge_cache
+            .layer(z)
+            .iter()
+            .map(|&v| if v == NO_VERTEX { None } else { Some(v) })
+            .collect()
+    }
+
+    pub fn finish(self) -> Mesh<T> {
+        Mesh(self.verts, self.tris, self.normals)
+    }
+}
+
+const NO_VERTEX: usize = usize::MAX;
+
+// Indices of vertices welded to the edges of the current slab of cubes. Edges on corner layers are
+// stored in `layers`, two slots for each corner (x- and y-directed edge starting from the corner).
+// Edges between the layers are in `z_edges`, one slot per corner. With face diagonals, layers
+// have a third slot for the xy-diagonal and `z_edges` two more for the xz- and yz-diagonals. When
+// marching continues to the next slab, the top layer becomes the bottom one, so memory use only
+// depends on the size of a layer.
+struct EdgeCache {
+    corner_count: (usize, usize),
+    diagonals: bool,
+    // Corner layer of `layers[0]`, `layers[1]` is the one above it
+    z: Option<usize>,
+    layers: [Vec<usize>; 2],
+    z_edges: Vec<usize>,
+}
+
+impl EdgeCache {
+    fn new(corner_count_x: usize, corner_count_y: usize, diagonals: bool) -> EdgeCache {
+        let n = corner_count_x * corner_count_y;
+        let (layer_slots, z_slots) = if diagonals { (3, 3) } else { (2, 1) };
+        EdgeCache {
+            corner_count: (corner_count_x, corner_count_y),
+            diagonals,
+            z: None,
+            layers: [vec![NO_VERTEX; layer_slots * n], vec![NO_VERTEX; layer_slots * n]],
+            z_edges: vec![NO_VERTEX; z_slots * n],
+        }
+    }
+
+    fn start_slab(&mut self, z: usize) {
+        if self.z.is_some_and(|prev_z| prev_z + 1 == z) {
+            self.layers.swap(0, 1);
+            self.layers[1].fill(NO_VERTEX);
+        } else {
+            self.layers[0].fill(NO_VERTEX);
+            self.layers[1].fill(NO_VERTEX);
+        }
+        self.z_edges.fill(NO_VERTEX);
+        self.z = Some(z);
+    }
+
+    // Slot of an edge given by `grid_shared_edge_index`, or of a face diagonal given by
+    // `grid_shared_vertex_index`, which has to be in the current slab
+    fn slot(&mut self, edge: (usize, usize, usize, usize)) -> &mut usize {
+        let (x, y, z, axis) = edge;
+        let i = x + self.corner_count.0 * y;
+        let (layer_slots, z_slots) = if self.diagonals { (3, 3) } else { (2, 1) };
+        match axis {
+            0 | 1 => &mut self.layers[z - self.z.unwrap()][layer_slots * i + axis],
+            3 => &mut self.layers[z - self.z.unwrap()][layer_slots * i + 2],
+            2 => &mut self.z_edges[z_slots * i],
+            _ => &mut self.z_edges[z_slots * i + axis - 3],
+        }
+    }
+
+    #[cfg(any(test, feature = "parallel"))]
+    fn layer(&self, z: usize) -> &[usize] {
+        &self.layers[z - self.z.unwrap()]
+    }
+}
+
+// Size of a cube when `cube_count` cubes are spread evenly between the bounds
+pub fn cube_size<T: Real>(
+    min_bound: &(T, T, T),
+    max_bound: &(T, T, T),
+    cube_count: &(usize, usize, usize),
+) -> (T, T, T) {
+    (
+        (max_bound.0 - min_bound.0) / T::from_usize(cube_count.0),
+        (max_bound.1 - min_bound.1) / T::from_usize(cube_count.1),
+        (max_bound.2 - min_bound.2) / T::from_usize(cube_count.2),
+    )
+}
+
+// Positions of the corners of cube `(x, y, z)` in the order of `CORNERS`, placed by the field or
+// spread from `min_bound` in steps of `cube_size`
+pub fn cube_positions<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    min_bound: &(T, T, T),
+    cube_size: &(T, T, T),
+    x: usize,
+    y: usize,
+    z: usize,
+) -> [(T, T, T); 8] {
+    let mut p = [(T::zero(), T::zero(), T::zero()); 8];
+    for (p, &(dx, dy, dz)) in p.iter_mut().zip(&CORNERS) {
+        let (cx, cy, cz) = (x + dx, y + dy, z + dz);
+        *p = field.position(cx, cy, cz).unwrap_or((
+            min_bound.0 + T::from_usize(cx) * cube_size.0,
+            min_bound.1 + T::from_usize(cy) * cube_size.1,
+            min_bound.2 + T::from_usize(cz) * cube_size.2,
+        ));
+    }
+    p
+}
+
+// Field values at the corners of cube `(x, y, z)` in the order of `CORNERS`
+pub fn cube_values<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    x: usize,
+    y: usize,
+    z: usize,
+) -> [T; 8] {
+    [
+        field.f(x, y, z),
+        field.f(x + 1, y, z),
+        field.f(x + 1, y, z + 1),
+        field.f(x, y, z + 1),
+        field.f(x, y + 1, z),
+        field.f(x + 1, y + 1, z),
+        field.f(x + 1, y + 1, z + 1),
+        field.f(x, y + 1, z + 1),
+    ]
+}
+
+// World space gradients at the corners of cube `(x, y, z)` with corner positions `p`
+pub fn cube_gradients<T: Real, F: ScalarField<T> + ?Sized>(
+    field: &F,
+    p: &[(T, T, T)],
+    x: usize,
+    y: usize,
+    z: usize,
+) -> [(T, T, T); 8] {
+    let mut g = [(T::zero(), T::zero(), T::zero()); 8];
+    for (g, &(dx, dy, dz)) in g.iter_mut().zip(&CORNERS) {
+        *g = world_gradient(p, field.gradient(x + dx, y + dy, z + dz));
+    }
+    g
+}
+
+// Converts gradient in grid units to world space, using the edges of cube `p` as the local basis
+// of the grid. This is the inverse transpose of the grid to world mapping, scaled by its
+// determinant.
+pub fn world_gradient<T: Real>(p: &[(T, T, T)], g: (T, T, T)) -> (T, T, T) {
+    let sub = |a: &(T, T, T), b: &(T, T, T)| (a.0 - b.0, a.1 - b.1, a.2 - b.2);
+    let cross = |a: &(T, T, T), b: &(T, T, T)| {
+        (a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0)
+    };
+    let (a, b, c) = (sub(&p[1], &p[0]), sub(&p[4], &p[0]), sub(&p[3], &p[0]));
+    let (bc, ca, ab) = (cross(&b, &c), cross(&c, &a), cross(&a, &b));
+    let det = a.0 * bc.0 + a.1 * bc.1 + a.2 * bc.2;
+    (
+        (g.0 * bc.0 + g.1 * ca.0 + g.2 * ab.0) / det,
+        (g.0 * bc.1 + g.1 * ca.1 + g.2 * ab.1) / det,
+        (g.0 * bc.2 + g.1 * ca.2 + g.2 * ab.2) / det,
+    )
+}
+
+// Determinant of the mapping from grid to world space at cube `p`. Negative for reflections.
+pub fn determinant<T: Real>(p: &[(T, T, T)]) -> T {
+    let sub = |a: &(T, T, T), b: &(T, T, T)| (a.0 - b.0, a.1 - b.1, a.2 - b.2);
+    let (a, b, c) = (sub(&p[1], &p[0]), sub(&p[4], &p[0]), sub(&p[3], &p[0]));
+    a.0 * (b.1 * c.2 - b.2 * c.1) + a.1 * (b.2 * c.0 - b.0 * c.2) + a.2 * (b.0 * c.1 - b.1 * c.0)
+}
+
+// Grid edge or face diagonal of cube vertex `i`, or `None` for vertices inside the cube. Face
+// diagonals are given like edges, with axis 3, 4 and 5 for diagonals in the xy-, xz- and yz-plane
+// starting from the corner.
+fn grid_shared_vertex_index(
+    cube_x: usize,
+    cube_y: usize,
+    cube_z: usize,
+    i: usize,
+) -> Option<(usize, usize, usize, usize)> {
+    match i {
+        0..=11 => Some(grid_shared_edge_index(cube_x, cube_y, cube_z, i)),
+        16 => Some((cube_x, cube_y, cube_z, 3)),
+        17 => Some((cube_x, cube_y, cube_z + 1, 3)),
+        18 => Some((cube_x, cube_y, cube_z, 4)),
+        19 => Some((cube_x, cube_y + 1, cube_z, 4)),
+        20 => Some((cube_x, cube_y, cube_z, 5)),
+        21 => Some((cube_x + 1, cube_y, cube_z, 5)),
+        _ => None,
+    }
 }
 
 fn grid_shared_edge_index(
@@ -116,10 +442,11 @@ fn grid_shared_edge_index(
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
 
-    use mesh::Vertex;
+    use crate::field::Bounds;
+    use crate::field::Field;
 
     #[test]
     fn test_edge_index() {
@@ -229,20 +556,43 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_edge_cache() {
+        let mut cache = EdgeCache::new(3, 2, false);
+        cache.start_slab(0);
+        *cache.slot(grid_shared_edge_index(1, 0, 0, 6)) = 1;
+        *cache.slot(grid_shared_edge_index(1, 0, 0, 5)) = 2;
+        assert_eq!(1, *cache.slot(grid_shared_edge_index(1, 1, 1, 0)));
+        assert_eq!(2, *cache.slot(grid_shared_edge_index(2, 0, 0, 7)));
+        assert_eq!(NO_VERTEX, *cache.slot(grid_shared_edge_index(1, 0, 0, 4)));
+
+        // Top layer becomes the bottom one
+        cache.start_slab(1);
+        assert_eq!(1, *cache.slot(grid_shared_edge_index(1, 0, 1, 4)));
+        assert_eq!(NO_VERTEX, *cache.slot(grid_shared_edge_index(1, 0, 1, 5)));
+        assert_eq!(NO_VERTEX, *cache.slot(grid_shared_edge_index(1, 0, 1, 6)));
+        assert_eq!(1, cache.layer(1)[8]);
+    }
+
     #[test]
     fn test_sphere() {
         //let sfield = SphereField::new(0.98);
         let r = 0.98;
+        let mesh = sphere_mesh(r);
 
+        assert_is_sphere(&mesh, r);
+    }
+
+    // Sphere of radius `r` meshed in 50x50x50 grid spanning (-1,-1,-1)-(1,1,1). Also used as a
+    // fixture by tests of other modules.
+    pub fn sphere_mesh(r: f32) -> Mesh {
         let field_table = Field::from_closure(
             |x,y,z| (x*x+y*y+z*z).sqrt() - r,
             &(-1.0, -1.0, -1.0),
             &(1.0, 1.0, 1.0),
             &(50, 50, 50)
         );
-        let mesh = create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0));
-
-        assert_is_sphere(&mesh, r);
+        create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0)).unwrap()
     }
 
     #[test]
@@ -252,7 +602,7 @@ mod tests {
             |x,y,z| (x*x+y*y+z*z).sqrt() - r,
             &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0), &(2, 2, 2)
         );
-        let mesh = create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0));
+        let mesh = create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0)).unwrap();
 
         assert_is_octahedron(&mesh, r);
     }
@@ -260,10 +610,329 @@ mod tests {
     #[test]
     fn test_precomputed() {
         let field = field_precomputed();
-        let mesh = create_mesh(&field, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0));
+        let mesh = create_mesh(&field, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0)).unwrap();
         assert_is_octahedron(&mesh, 0.5);
     }
 
+    #[test]
+    fn test_iso_value() {
+        // Shells of the same distance field at different radii
+        let field_table = Field::from_closure(
+            |x,y,z| (x*x+y*y+z*z).sqrt(),
+            &(-1.0, -1.0, -1.0),
+            &(1.0, 1.0, 1.0),
+            &(50, 50, 50)
+        );
+        for &r in &[0.5, 0.75, 0.98] {
+            let mesh =
+                create_mesh_iso(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0), r).unwrap();
+            assert_is_sphere(&mesh, r);
+        }
+
+        let field = field_precomputed();
+        let mesh = create_mesh_iso(&field, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0), 0.5).unwrap();
+        assert_is_octahedron(&mesh, 0.25);
+    }
+
+    #[test]
+    fn test_normals() {
+        let r = 0.98;
+        let field_table = Field::from_closure(
+            |x,y,z| (x*x+y*y+z*z).sqrt() - r,
+            &(-1.0, -1.0, -1.0),
+            &(1.0, 1.0, 1.0),
+            &(50, 50, 50)
+        );
+        let options = MeshOptions {
+            normals: true,
+            ..MeshOptions::default()
+        };
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let mesh = create_mesh_with(&field_table, &min, &max, &options).unwrap();
+        assert_eq!(mesh.0.len(), mesh.2.len());
+
+        // Normals of a sphere point along the radius vector
+        for (v, n) in mesh.0.iter().zip(&mesh.2) {
+            let l = (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+            let d = (v.0 * n.0 + v.1 * n.1 + v.2 * n.2) / l;
+            assert!(d > 0.99);
+        }
+
+        let mesh = create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0)).unwrap();
+        assert!(!mesh.has_normals());
+    }
+
+    // Sphere evaluated on demand, placed in world space by the field itself
+    struct ProceduralSphere {
+        r: f32,
+        n: usize,
+    }
+
+    impl ScalarField for ProceduralSphere {
+        fn corner_count(&self) -> (usize, usize, usize) {
+            (self.n + 1, self.n + 1, self.n + 1)
+        }
+        fn f(&self, x: usize, y: usize, z: usize) -> f32 {
+            let (x, y, z) = self.position(x, y, z).unwrap();
+            (x * x + y * y + z * z).sqrt() - self.r
+        }
+        fn position(&self, x: usize, y: usize, z: usize) -> Option<(f32, f32, f32)> {
+            let s = 2.0 / (self.n as f32);
+            Some((x as f32 * s - 1.0, y as f32 * s - 1.0, z as f32 * s - 1.0))
+        }
+    }
+
+    #[test]
+    fn test_scalar_field() {
+        let field = ProceduralSphere { r: 0.98, n: 50 };
+
+        // Bounds are ignored, as the field places itself
+        let mesh = create_mesh(&field, &(0.0, 0.0, 0.0), &(1.0, 1.0, 1.0)).unwrap();
+        assert_is_sphere(&mesh, 0.98);
+
+        let field_table = Field::from_closure(
+            |x,y,z| (x*x+y*y+z*z).sqrt() - 0.98,
+            &(-1.0, -1.0, -1.0),
+            &(1.0, 1.0, 1.0),
+            &(50, 50, 50)
+        );
+        let table_mesh =
+            create_mesh(&field_table, &(-1.0, -1.0, -1.0), &(1.0, 1.0, 1.0)).unwrap();
+        assert_eq!(mesh.0.len(), table_mesh.0.len());
+        assert_eq!(mesh.1, table_mesh.1);
+
+        // Also works through a trait object
+        let dyn_field: &dyn ScalarField = &field;
+        let options = MeshOptions {
+            normals: true,
+            ..MeshOptions::default()
+        };
+        let mesh =
+            create_mesh_with(dyn_field, &(0.0, 0.0, 0.0), &(1.0, 1.0, 1.0), &options).unwrap();
+        for (v, n) in mesh.0.iter().zip(&mesh.2) {
+            assert!((v.0 * n.0 + v.1 * n.1 + v.2 * n.2) / 0.98 > 0.99);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let field = field_precomputed();
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        assert!(create_mesh(&field, &min, &max).is_ok());
+        assert_eq!(Some(Error::InvalidBounds), create_mesh(&field, &max, &min).err());
+        let flat = (-1.0, 1.0, -1.0);
+        assert_eq!(Some(Error::InvalidBounds), create_mesh(&field, &min, &flat).err());
+        let nan = (-1.0, f32::NAN, -1.0);
+        assert_eq!(Some(Error::InvalidBounds), create_mesh(&field, &nan, &max).err());
+
+        // Bounds don't matter when the field places itself
+        let sphere = ProceduralSphere { r: 0.5, n: 4 };
+        assert!(create_mesh(&sphere, &max, &min).is_ok());
+        let sphere = ProceduralSphere { r: 0.5, n: 0 };
+        assert_eq!(
+            Some(Error::TooFewCorners {
+                corner_count: (1, 1, 1)
+            }),
+            create_mesh(&sphere, &min, &max).err()
+        );
+        let flat_field = field_precomputed().with_spacing((1.0, 0.0, 1.0));
+        assert_eq!(
+            Some(Error::DegenerateGrid),
+            create_mesh(&flat_field, &min, &max).err()
+        );
+    }
+
+    // Pseudo-random values in [-1, 1) inside the grid, and 1 on its boundary so that the surface
+    // is closed
+    pub fn noise_field(n: usize) -> Field<f64> {
+        let mut seed = 1u32;
+        let mut data = Vec::with_capacity(n * n * n);
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    let boundary = [x, y, z].iter().any(|&c| c == 0 || c == n - 1);
+                    data.push(if boundary {
+                        1.0
+                    } else {
+                        (seed >> 16) as f64 / 32768.0 - 1.0
+                    });
+                }
+            }
+        }
+        Field::from_flat((n, n, n), data)
+    }
+
+    #[test]
+    fn test_ambiguous_face() {
+        // Two cubes sharing a face with inside corners on one diagonal. Their values dominate the
+        // saddle of the face, so both cubes join them into one closed surface around the diagonal.
+        let mut data = vec![2.0; 3 * 4 * 4];
+        for &(x, y, z, v) in &[(1, 1, 1, -2.0), (1, 2, 2, -2.0), (1, 2, 1, 0.5), (1, 1, 2, 0.5)] {
+            data[x + y * 3 + z * 3 * 4] = v;
+        }
+        let field = Field::from_flat((3, 4, 4), data);
+        let mesh = create_mesh(&field, &(0.0, 0.0, 0.0), &(2.0, 3.0, 3.0)).unwrap();
+        assert!(mesh.is_closed());
+        assert_eq!(1, mesh.components().len());
+
+        // Adjacent cubes agree on every face of a noisy field
+        let mesh = create_mesh(&noise_field(12), &(0.0, 0.0, 0.0), &(1.0, 1.0, 1.0)).unwrap();
+        assert!(mesh.is_closed());
+    }
+
+    #[test]
+    fn test_mc33() {
+        let field = noise_field(12);
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let options = MeshOptions {
+            normals: true,
+            tessellation: Tessellation::Mc33,
+            ..MeshOptions::default()
+        };
+        let mesh = create_mesh_with(&field, &min, &max, &options).unwrap();
+        assert!(mesh.is_closed());
+        assert_eq!(mesh.0.len(), mesh.2.len());
+
+        // Closed surface facing outwards encloses a positive volume
+        assert!(volume(&mesh) > 0.0);
+
+        // Without ambiguities, both give the same vertices
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let sphere = Field::from_closure(
+            |x, y, z| (x * x + y * y + z * z).sqrt() - 0.98,
+            &min,
+            &max,
+            &(50, 50, 50),
+        );
+        let classic = create_mesh(&sphere, &min, &max).unwrap();
+        let options = MeshOptions {
+            tessellation: Tessellation::Mc33,
+            ..MeshOptions::default()
+        };
+        let mesh = create_mesh_with(&sphere, &min, &max, &options).unwrap();
+        assert_eq!(classic.0.len(), mesh.0.len());
+        assert_eq!(classic.1.len(), mesh.1.len());
+        assert_is_sphere(&mesh, 0.98);
+    }
+
+    #[test]
+    fn test_tetrahedra() {
+        let options = MeshOptions {
+            normals: true,
+            tessellation: Tessellation::Tetrahedra,
+            ..MeshOptions::default()
+        };
+        let (min, max) = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let mesh = create_mesh_with(&noise_field(12), &min, &max, &options).unwrap();
+        assert!(mesh.is_closed());
+        assert_eq!(mesh.0.len(), mesh.2.len());
+
+        // Same sphere as with the cube table, with more triangles
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let sphere = Field::from_closure(
+            |x, y, z| (x * x + y * y + z * z).sqrt() - 0.98,
+            &min,
+            &max,
+            &(50, 50, 50),
+        );
+        let classic = create_mesh(&sphere, &min, &max).unwrap();
+        let options = MeshOptions {
+            tessellation: Tessellation::Tetrahedra,
+            ..MeshOptions::default()
+        };
+        let mesh = create_mesh_with(&sphere, &min, &max, &options).unwrap();
+        assert!(mesh.is_closed());
+        assert!(mesh.1.len() > classic.1.len());
+        for v in &mesh.0 {
+            assert!(((v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt() - 0.98).abs() < 0.002);
+        }
+        assert!((volume(&mesh) / volume(&classic) - 1.0).abs() < 0.001);
+    }
+
+    // Signed volume enclosed by a closed mesh, positive when the triangles face outwards. Also used
+    // by tests of other modules.
+    pub fn volume<T: Real>(mesh: &Mesh<T>) -> f64 {
+        let to_f64 = |v: &Vertex<T>| (v.0.to_f64(), v.1.to_f64(), v.2.to_f64());
+        let volume: f64 = mesh
+            .1
+            .iter()
+            .map(|t| {
+                let (a, b, c) = (to_f64(&mesh.0[t.0]), to_f64(&mesh.0[t.1]), to_f64(&mesh.0[t.2]));
+                a.0 * (b.1 * c.2 - b.2 * c.1) + a.1 * (b.2 * c.0 - b.0 * c.2)
+                    + a.2 * (b.0 * c.1 - b.1 * c.0)
+            })
+            .sum();
+        volume / 6.0
+    }
+
+    #[test]
+    fn test_placed_field() {
+        let (min, max) = ((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let mesh = create_mesh(&field_precomputed(), &min, &max).unwrap();
+
+        // Field carrying its own placement ignores the bounds given to `create_mesh`
+        let bounds = Bounds { min, max };
+        let field = field_precomputed().with_bounds(&bounds);
+        let placed_mesh = create_mesh(&field, &max, &min).unwrap();
+        assert_eq!(mesh.1, placed_mesh.1);
+        for (a, b) in mesh.0.iter().zip(&placed_mesh.0) {
+            assert!((a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs() < 1e-5);
+        }
+
+        // Closure fields are placed where they were sampled
+        let field = Field::from_closure(
+            |x, y, z| (x * x + y * y + z * z).sqrt() - 0.98,
+            &min,
+            &max,
+            &(50, 50, 50),
+        );
+        let mesh = create_mesh(&field, &(0.0, 0.0, 0.0), &(1.0, 1.0, 1.0)).unwrap();
+        assert_is_sphere(&mesh, 0.98);
+
+        // Field placing only its first corner
+        struct PartiallyPlaced(Field);
+        impl ScalarField for PartiallyPlaced {
+            fn corner_count(&self) -> (usize, usize, usize) {
+                self.0.corner_count()
+            }
+            fn f(&self, x: usize, y: usize, z: usize) -> f32 {
+                self.0.f(x, y, z)
+            }
+            fn position(&self, x: usize, y: usize, z: usize) -> Option<(f32, f32, f32)> {
+                Some((0.0, 0.0, 0.0)).filter(|_| (x, y, z) == (0, 0, 0))
+            }
+        }
+        let field = PartiallyPlaced(field_precomputed());
+        assert_eq!(Some(Error::DegenerateGrid), create_mesh(&field, &min, &max).err());
+    }
+
+    #[test]
+    fn test_f64() {
+        // Far away from origin, f32 wouldn't have enough precision to represent the sphere
+        let c = 1.0e6;
+        let r = 0.98;
+        let (min, max) = ((c - 1.0, c - 1.0, c - 1.0), (c + 1.0, c + 1.0, c + 1.0));
+        let field = Field::<f64>::from_fn(
+            |x, y, z| ((x - c) * (x - c) + (y - c) * (y - c) + (z - c) * (z - c)).sqrt() - r,
+            &min,
+            &max,
+            &(20, 20, 20),
+        );
+        let options = MeshOptions {
+            normals: true,
+            ..MeshOptions::default()
+        };
+        let mesh: Mesh<f64> = create_mesh_with(&field, &min, &max, &options).unwrap();
+        assert!(!mesh.1.is_empty());
+        for (v, n) in mesh.0.iter().zip(&mesh.2) {
+            let d = (v.0 - c, v.1 - c, v.2 - c);
+            let l = (d.0 * d.0 + d.1 * d.1 + d.2 * d.2).sqrt();
+            assert!((l - r).abs() < 0.01);
+            assert!((d.0 * n.0 + d.1 * n.1 + d.2 * n.2) / l > 0.99);
+        }
+    }
+
     fn assert_is_sphere(mesh: &Mesh, r: f32) {
         // All vertices are within radius r
         for vert in &mesh.0 {
@@ -288,12 +957,7 @@ mod tests {
                 (v0.1 + v1.1 + v2.1) / 3.0,
                 (v0.2 + v1.2 + v2.2) / 3.0,
             );
-            let nd = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
-            //let n = (n.0 / nd, n.1 / nd, n.2 / nd);
             let d = n.0 * rv.0 + n.1 * rv.1 + n.2 * rv.2;
-            if nd / 2.0 > (2.0 / 50.0) * (2.0 / 50.0) {
-                println!("AREA: {}", nd);
-            }
             if d < 0.0 {
                 println!("{}", d);
                 fail = true;
@@ -0,0 +1,122 @@
+
+use crate::cube::segment_point;
+use crate::real::Real;
+
+use crate::tables::DIAGONALS;
+use crate::tables::EDGES;
+use crate::tables::TETRAHEDRA;
+use crate::tables::TETRAHEDRA_TO_CUBE_EDGES;
+use crate::tables::TETRAHEDRON_EDGES;
+use crate::tables::TETRAHEDRON_VERTS_INSIDE_TO_TRIS;
+
+// Index of the vertex on the first diagonal in `DIAGONALS`, after the 12 edge vertices and the
+// vertices inside the cube used by MC33. The last diagonal goes through the inside of the cube,
+// so its vertex isn't shared with other cubes.
+pub const DIAGONAL_VERTEX: usize = 16;
+
+// Tessellates a cube like `tessellate_corners_into`, but by splitting it into the tetrahedra of
+// `TETRAHEDRA` and tessellating each of them. Tetrahedra have no ambiguous configurations, so the
+// result can be used as a reference for the cube table, at the cost of about twice as many
+// triangles. Triangles use vertices on the face and body diagonals of the cube, which are written
+// to `verts` from `DIAGONAL_VERTEX` onwards. Has the same requirements for `verts` and `tris` as
+// `tessellate_corners_into`.
+pub fn tessellate_tetrahedra_into<T: Real>(
+    p: &[(T, T, T)],
+    f: &[T],
+    iso: T,
+    verts: &mut [(T, T, T)],
+    tris: &mut [(usize, usize, usize)],
+) -> usize {
+    let mut tri_count = 0;
+    for (tet, cube_edges) in TETRAHEDRA.iter().zip(&TETRAHEDRA_TO_CUBE_EDGES) {
+        let verts_in = (0..4).filter(|&i| f[tet[i]] < iso).fold(0, |c, i| c | (1 << i));
+        for &(e0, e1, e2) in TETRAHEDRON_VERTS_INSIDE_TO_TRIS[verts_in].iter().flatten() {
+            for &e in &[e0, e1, e2] {
+                let (v0, v1) = TETRAHEDRON_EDGES[e];
+                verts[cube_edges[e]] = segment_point((tet[v0], tet[v1]), p, f, iso);
+            }
+            tris[tri_count] = (cube_edges[e0], cube_edges[e1], cube_edges[e2]);
+            tri_count += 1;
+        }
+    }
+    tri_count
+}
+
+// Cube vertex index pair of edge or diagonal `i`
+pub fn segment(i: usize) -> (usize, usize) {
+    if i < DIAGONAL_VERTEX {
+        EDGES[i]
+    } else {
+        DIAGONALS[i - DIAGONAL_VERTEX]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cube::CUBE_TRI_COUNT;
+    use crate::cube::CUBE_VERT_COUNT;
+    use crate::tables::CORNERS;
+
+    #[test]
+    fn test_tables() {
+        let corner = |i: usize| {
+            let (x, y, z) = CORNERS[i];
+            (x as i32, y as i32, z as i32)
+        };
+        for (tet, cube_edges) in TETRAHEDRA.iter().zip(&TETRAHEDRA_TO_CUBE_EDGES) {
+            // Positive volume
+            let (a, b, c, d) = (corner(tet[0]), corner(tet[1]), corner(tet[2]), corner(tet[3]));
+            let (u, v, w) = (
+                (b.0 - a.0, b.1 - a.1, b.2 - a.2),
+                (c.0 - a.0, c.1 - a.1, c.2 - a.2),
+                (d.0 - a.0, d.1 - a.1, d.2 - a.2),
+            );
+            let det = u.0 * (v.1 * w.2 - v.2 * w.1) + u.1 * (v.2 * w.0 - v.0 * w.2)
+                + u.2 * (v.0 * w.1 - v.1 * w.0);
+            assert_eq!(1, det);
+
+            // Edges map to the cube edges between the same corners
+            for (&(v0, v1), &i) in TETRAHEDRON_EDGES.iter().zip(cube_edges) {
+                let (c0, c1) = segment(i);
+                let (t0, t1) = (tet[v0], tet[v1]);
+                assert!((c0, c1) == (t0, t1) || (c0, c1) == (t1, t0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_linear_field() {
+        // Iso-surface of a linear field is a plane, and every triangle faces along the gradient
+        let p: Vec<_> = CORNERS
+            .iter()
+            .map(|&(x, y, z)| (x as f64, y as f64, z as f64))
+            .collect();
+        let mut verts = [(0.0, 0.0, 0.0); CUBE_VERT_COUNT];
+        let mut tris = [(0, 0, 0); CUBE_TRI_COUNT];
+        for &(gx, gy, gz, d) in &[
+            (1.0, 0.2, 0.3, 0.5),
+            (-0.4, 1.0, 0.1, 0.2),
+            (0.3, -0.6, -1.0, -0.7),
+            (1.0, 1.0, 1.0, 1.3),
+            (-1.0, 0.5, 1.0, 0.1),
+        ] {
+            let f: Vec<f64> = p.iter().map(|p| gx * p.0 + gy * p.1 + gz * p.2 - d).collect();
+            let tri_count = tessellate_tetrahedra_into(&p, &f, 0.0, &mut verts, &mut tris);
+            assert!(tri_count > 0);
+            for &(i0, i1, i2) in &tris[..tri_count] {
+                let (a, b, c) = (verts[i0], verts[i1], verts[i2]);
+                let (u, v) = ((b.0 - a.0, b.1 - a.1, b.2 - a.2), (c.0 - a.0, c.1 - a.1, c.2 - a.2));
+                let n = (u.1 * v.2 - u.2 * v.1, u.2 * v.0 - u.0 * v.2, u.0 * v.1 - u.1 * v.0);
+                let area = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
+                if area > 1e-12 {
+                    assert!(n.0 * gx + n.1 * gy + n.2 * gz > 0.0);
+                }
+                for v in &[a, b, c] {
+                    assert!((gx * v.0 + gy * v.1 + gz * v.2 - d).abs() < 1e-9);
+                }
+            }
+        }
+    }
+}
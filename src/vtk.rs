@@ -0,0 +1,442 @@
+
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::mem;
+
+use crate::error::invalid_data;
+use crate::field::total_corner_count;
+use crate::field::Bounds;
+use crate::field::Field;
+use crate::mesh::Mesh;
+use crate::ply::check_attributes;
+use crate::ply::VertexAttributes;
+use crate::raw::decode_samples;
+use crate::raw::Endianness;
+use crate::raw::SampleType;
+use crate::real::Real;
+
+const LOOKUP_TABLE: &[u8] = b"LOOKUP_TABLE";
+
+// Encoding of data in a legacy VTK file. Binary data is big-endian.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VtkFormat {
+    Ascii,
+    Binary,
+}
+
+impl<T: Real> Field<T> {
+    // Reads a legacy VTK STRUCTURED_POINTS dataset in ASCII or binary format. The first point data
+    // scalar array is used as the field, which is placed in world space by `ORIGIN` and `SPACING`.
+    // The returned bounds enclose its grid.
+    pub fn read_vtk<R: BufRead>(mut r: R) -> io::Result<(Field<T>, Bounds<T>)> {
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if !line.starts_with("# vtk DataFile") {
+            return Err(invalid_data("Not a legacy VTK file"));
+        }
+        // Title
+        line.clear();
+        r.read_line(&mut line)?;
+        line.clear();
+        r.read_line(&mut line)?;
+        let format = match line.trim() {
+            "ASCII" => VtkFormat::Ascii,
+            "BINARY" => VtkFormat::Binary,
+            _ => return Err(invalid_data("Invalid VTK format")),
+        };
+
+        let mut dims = None;
+        let mut origin = (T::zero(), T::zero(), T::zero());
+        let mut spacing = (T::one(), T::one(), T::one());
+        let mut point_data = false;
+        let sample_type = loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Err(invalid_data("Missing VTK point data scalars"));
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [] => {}
+                ["DATASET", "STRUCTURED_POINTS"] => {}
+                ["DATASET", _] => return Err(invalid_data("Unsupported VTK dataset")),
+                ["DIMENSIONS", x, y, z] => dims = Some((parse(x)?, parse(y)?, parse(z)?)),
+                ["SPACING", x, y, z] | ["ASPECT_RATIO", x, y, z] => {
+                    spacing = (parse(x)?, parse(y)?, parse(z)?)
+                }
+                ["ORIGIN", x, y, z] => origin = (parse(x)?, parse(y)?, parse(z)?),
+                ["POINT_DATA", _] => point_data = true,
+                ["SCALARS", _, ty] | ["SCALARS", _, ty, "1"] if point_data => {
+                    break parse_type(ty)?;
+                }
+                _ => return Err(invalid_data("Unsupported VTK structured points data")),
+            }
+        };
+        let dims: (usize, usize, usize) =
+            dims.ok_or_else(|| invalid_data("Missing VTK DIMENSIONS"))?;
+        if dims.0 < 2 || dims.1 < 2 || dims.2 < 2 {
+            return Err(invalid_data("Invalid VTK DIMENSIONS"));
+        }
+
+        // Lookup table line is optional, so the start of the data is read ahead and put back in
+        // front of the reader unless it is the lookup table
+        let mut ahead = Vec::new();
+        (&mut r)
+            .take(LOOKUP_TABLE.len() as u64)
+            .read_to_end(&mut ahead)?;
+        if ahead == LOOKUP_TABLE {
+            line.clear();
+            r.read_line(&mut line)?;
+            ahead.clear();
+        }
+        let mut r = ahead.as_slice().chain(r);
+
+        // Buffers are grown as data is read instead of sized from the header, which can't be
+        // trusted before the data is there
+        let count =
+            total_corner_count(dims).ok_or_else(|| invalid_data("Invalid VTK DIMENSIONS"))?;
+        let mut values = Vec::new();
+        match format {
+            VtkFormat::Ascii => {
+                while values.len() < count {
+                    line.clear();
+                    if r.read_line(&mut line)? == 0 {
+                        return Err(invalid_data("Unexpected end of VTK data"));
+                    }
+                    for token in line.split_whitespace() {
+                        values.push(parse(token)?);
+                    }
+                }
+                values.truncate(count);
+            }
+            VtkFormat::Binary => {
+                let len = count
+                    .checked_mul(sample_type.size())
+                    .ok_or_else(|| invalid_data("Invalid VTK DIMENSIONS"))?;
+                let mut bytes = Vec::new();
+                r.take(len as u64).read_to_end(&mut bytes)?;
+                values = decode_samples(&bytes, dims, sample_type, Endianness::Big)?;
+            }
+        }
+        let field = Field::try_from_flat(dims, values)?
+            .with_origin(origin)
+            .with_spacing(spacing);
+        let bounds = field.bounds().unwrap();
+        Ok((field, bounds))
+    }
+}
+
+impl<T: Real> Mesh<T> {
+    // Writes the mesh as a legacy VTK POLYDATA dataset, with normals (if any) as point data. Fails
+    // with `InvalidInput` if a vertex index doesn't fit in the `int` of VTK.
+    pub fn write_vtk<W: Write>(&self, w: &mut W, format: VtkFormat) -> io::Result<()> {
+        self.write_vtk_with(w, format, &VertexAttributes::default())
+    }
+
+    // Same as `write_vtk`, with additional per-vertex colors and scalar arrays as point data
+    pub fn write_vtk_with<W: Write>(
+        &self,
+        w: &mut W,
+        format: VtkFormat,
+        attributes: &VertexAttributes<T>,
+    ) -> io::Result<()> {
+        let vert_count = self.0.len();
+        check_attributes(attributes, vert_count)?;
+        // Polygons are written as `int` lists
+        if self
+            .1
+            .iter()
+            .any(|t| t.0.max(t.1).max(t.2) > i32::MAX as usize)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Vertex index too large for VTK",
+            ));
+        }
+
+        let real = if mem::size_of::<T>() == 4 {
+            "float"
+        } else {
+            "double"
+        };
+        writeln!(w, "# vtk DataFile Version 3.0")?;
+        writeln!(w, "marching-cubes mesh")?;
+        match format {
+            VtkFormat::Ascii => writeln!(w, "ASCII")?,
+            VtkFormat::Binary => writeln!(w, "BINARY")?,
+        }
+        writeln!(w, "DATASET POLYDATA")?;
+        let mut out = VtkWriter { w, format };
+
+        writeln!(out.w, "POINTS {} {}", vert_count, real)?;
+        for v in &self.0 {
+            out.reals(&[v.0, v.1, v.2])?;
+        }
+        out.end_array()?;
+        writeln!(out.w, "POLYGONS {} {}", self.1.len(), 4 * self.1.len())?;
+        for t in &self.1 {
+            out.ints(&[3, t.0, t.1, t.2])?;
+        }
+        out.end_array()?;
+
+        let has_point_data =
+            self.has_normals() || !attributes.colors.is_empty() || !attributes.scalars.is_empty();
+        if has_point_data {
+            writeln!(out.w, "POINT_DATA {}", vert_count)?;
+        }
+        if self.has_normals() {
+            writeln!(out.w, "NORMALS normals {}", real)?;
+            for n in &self.2 {
+                out.reals(&[n.0, n.1, n.2])?;
+            }
+            out.end_array()?;
+        }
+        if !attributes.colors.is_empty() {
+            writeln!(out.w, "COLOR_SCALARS colors 3")?;
+            for &(r, g, b) in &attributes.colors {
+                out.colors(&[r, g, b])?;
+            }
+            out.end_array()?;
+        }
+        for (name, values) in &attributes.scalars {
+            writeln!(out.w, "SCALARS {} {} 1", name, real)?;
+            writeln!(out.w, "LOOKUP_TABLE default")?;
+            for &v in values {
+                out.reals(&[v])?;
+            }
+            out.end_array()?;
+        }
+        Ok(())
+    }
+}
+
+struct VtkWriter<'a, W: 'a> {
+    w: &'a mut W,
+    format: VtkFormat,
+}
+
+impl<'a, W: Write> VtkWriter<'a, W> {
+    // Writes a tuple of values, on its own line in ASCII format
+    fn reals<T: Real>(&mut self, values: &[T]) -> io::Result<()> {
+        match self.format {
+            VtkFormat::Ascii => self.line(values),
+            VtkFormat::Binary => {
+                for &v in values {
+                    if mem::size_of::<T>() == 4 {
+                        self.w.write_all(&(v.to_f64() as f32).to_be_bytes())?;
+                    } else {
+                        self.w.write_all(&v.to_f64().to_be_bytes())?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn ints(&mut self, values: &[usize]) -> io::Result<()> {
+        match self.format {
+            VtkFormat::Ascii => self.line(values),
+            VtkFormat::Binary => {
+                for &v in values {
+                    self.w.write_all(&(v as i32).to_be_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Colors are unsigned chars in binary format, but floats between 0 and 1 in ASCII format
+    fn colors(&mut self, values: &[u8]) -> io::Result<()> {
+        match self.format {
+            VtkFormat::Ascii => {
+                let values: Vec<f32> = values.iter().map(|&c| f32::from(c) / 255.0).collect();
+                self.line(&values)
+            }
+            VtkFormat::Binary => self.w.write_all(values),
+        }
+    }
+
+    fn line<V: ::std::fmt::Display>(&mut self, values: &[V]) -> io::Result<()> {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        writeln!(self.w, "{}", values.join(" "))
+    }
+
+    // Binary arrays are followed by a newline
+    fn end_array(&mut self) -> io::Result<()> {
+        if self.format == VtkFormat::Binary {
+            writeln!(self.w)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_type(name: &str) -> io::Result<SampleType> {
+    Ok(match name {
+        "unsigned_char" => SampleType::U8,
+        "unsigned_short" => SampleType::U16,
+        "short" => SampleType::I16,
+        "float" => SampleType::F32,
+        "double" => SampleType::F64,
+        _ => return Err(invalid_data("Unsupported VTK scalar type")),
+    })
+}
+
+fn parse<V: ::std::str::FromStr>(token: &str) -> io::Result<V> {
+    token.parse().map_err(|_| invalid_data("Invalid VTK value"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::ScalarField;
+    use crate::mesh::Triangle;
+    use crate::mesh::Vertex;
+    use crate::tessellator::tests::sphere_mesh;
+
+    const HEADER: &str = "# vtk DataFile Version 3.0\n\
+                          volume\n\
+                          FORMAT\n\
+                          DATASET STRUCTURED_POINTS\n\
+                          DIMENSIONS 3 2 2\n\
+                          ORIGIN 1 2 3\n\
+                          SPACING 0.5 1 2\n\
+                          POINT_DATA 12\n\
+                          SCALARS density TYPE 1\n\
+                          LOOKUP_TABLE default\n";
+
+    fn check(field: &Field, bounds: &Bounds) {
+        assert_eq!((3, 2, 2), field.corner_count());
+        let expected: Vec<f32> = (0..12).map(|i| i as f32 - 5.0).collect();
+        assert_eq!(expected.as_slice(), field.as_slice());
+        assert_eq!((1.0, 2.0, 3.0), bounds.min);
+        assert_eq!((2.0, 3.0, 5.0), bounds.max);
+        assert_eq!(Some((1.5, 3.0, 5.0)), field.position(1, 1, 1));
+    }
+
+    #[test]
+    fn test_read_structured_points() {
+        let ascii = HEADER.replace("FORMAT", "ASCII").replace("TYPE", "float")
+            + "-5 -4 -3 -2 -1 0\n1 2 3\n4 5 6\n";
+        let (field, bounds) = Field::read_vtk(ascii.as_bytes()).unwrap();
+        check(&field, &bounds);
+
+        let mut binary = HEADER
+            .replace("FORMAT", "BINARY")
+            .replace("TYPE", "short")
+            .into_bytes();
+        for i in -5i16..7 {
+            binary.extend_from_slice(&i.to_be_bytes());
+        }
+        binary.extend_from_slice(b"\n");
+        let (field, bounds) = Field::read_vtk(&binary[..]).unwrap();
+        check(&field, &bounds);
+
+        // Binary data right after the scalars, without a lookup table
+        let start = binary.len() - 25;
+        assert!(binary[..start].ends_with(b"LOOKUP_TABLE default\n"));
+        let mut without_table = binary.clone();
+        without_table.drain((start - 21)..start);
+        let (field, bounds) = Field::read_vtk(&without_table[..]).unwrap();
+        check(&field, &bounds);
+
+        let truncated = &binary[..(binary.len() - 3)];
+        assert!(Field::<f32>::read_vtk(truncated).is_err());
+
+        // Huge dimensions fail on the missing data instead of allocating for it
+        let header = HEADER
+            .replace("FORMAT", "BINARY")
+            .replace("TYPE", "short")
+            .replace("DIMENSIONS 3 2 2", "DIMENSIONS 1048576 1048576 1024");
+        let error = Field::<f32>::read_vtk(header.as_bytes()).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        let header = header.replace("1048576 1048576", "18446744073709551615 2");
+        assert!(Field::<f32>::read_vtk(header.as_bytes()).is_err());
+        let ascii = HEADER
+            .replace("FORMAT", "ASCII")
+            .replace("TYPE", "float")
+            .replace("DIMENSIONS 3 2 2", "DIMENSIONS 1048576 1048576 1024");
+        assert!(Field::<f32>::read_vtk(ascii.as_bytes()).is_err());
+        let rectilinear = ascii.replace("STRUCTURED_POINTS", "RECTILINEAR_GRID");
+        assert!(Field::<f32>::read_vtk(rectilinear.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_write_polydata_ascii() {
+        let mut mesh = sphere_mesh(0.9);
+        mesh.compute_vertex_normals();
+        let attributes = VertexAttributes {
+            colors: vec![(255, 0, 51); mesh.0.len()],
+            scalars: vec![("height".to_string(), mesh.0.iter().map(|v| v.2).collect())],
+        };
+        let mut vtk = Vec::new();
+        mesh.write_vtk_with(&mut vtk, VtkFormat::Ascii, &attributes)
+            .unwrap();
+        let vtk = String::from_utf8(vtk).unwrap();
+        let lines: Vec<&str> = vtk.lines().collect();
+        let (n, m) = (mesh.0.len(), mesh.1.len());
+
+        assert_eq!("DATASET POLYDATA", lines[3]);
+        assert_eq!(format!("POINTS {} float", n), lines[4]);
+        let v = &mesh.0[1];
+        assert_eq!(format!("{} {} {}", v.0, v.1, v.2), lines[6]);
+        assert_eq!(format!("POLYGONS {} {}", m, 4 * m), lines[5 + n]);
+        let t = &mesh.1[0];
+        assert_eq!(format!("3 {} {} {}", t.0, t.1, t.2), lines[6 + n]);
+
+        let point_data = 6 + n + m;
+        assert_eq!(format!("POINT_DATA {}", n), lines[point_data]);
+        assert_eq!("NORMALS normals float", lines[point_data + 1]);
+        let normal = &mesh.2[0];
+        let expected = format!("{} {} {}", normal.0, normal.1, normal.2);
+        assert_eq!(expected, lines[point_data + 2]);
+        assert_eq!("COLOR_SCALARS colors 3", lines[point_data + 2 + n]);
+        assert_eq!("1 0 0.2", lines[point_data + 3 + n]);
+        assert_eq!("SCALARS height float 1", lines[point_data + 3 + 2 * n]);
+        assert_eq!(format!("{}", mesh.0[0].2), lines[point_data + 5 + 2 * n]);
+        assert_eq!(point_data + 5 + 3 * n, lines.len());
+
+        let attributes = VertexAttributes {
+            colors: vec![(255, 0, 51); mesh.0.len() - 1],
+            scalars: Vec::new(),
+        };
+        let result = mesh.write_vtk_with(&mut Vec::new(), VtkFormat::Ascii, &attributes);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+    }
+
+    #[test]
+    fn test_write_polydata_binary() {
+        let mesh = Mesh::new(
+            vec![
+                Vertex(0.0f64, 0.0, 0.0),
+                Vertex(1.0, 0.0, 0.0),
+                Vertex(0.0, 1.0, 0.5),
+            ],
+            vec![Triangle(0, 1, 2)],
+        );
+        let mut vtk = Vec::new();
+        mesh.write_vtk(&mut vtk, VtkFormat::Binary).unwrap();
+
+        let header = "# vtk DataFile Version 3.0\nmarching-cubes mesh\nBINARY\n\
+                      DATASET POLYDATA\nPOINTS 3 double\n";
+        assert!(vtk.starts_with(header.as_bytes()));
+        let points = &vtk[header.len()..];
+        let mut z = [0u8; 8];
+        z.copy_from_slice(&points[64..72]);
+        assert_eq!(0.5, f64::from_be_bytes(z));
+        assert_eq!(b'\n', points[72]);
+
+        let polygons = &points[73..];
+        assert!(polygons.starts_with(b"POLYGONS 1 4\n"));
+        let indices = &polygons[13..];
+        assert_eq!([0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1], indices[..12]);
+        assert_eq!(13 + 16 + 1, polygons.len());
+
+        let mesh = Mesh::new(mesh.0, vec![Triangle(0, 1, usize::MAX)]);
+        let mut vtk = Vec::new();
+        let result = mesh.write_vtk(&mut vtk, VtkFormat::Binary);
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        assert!(vtk.is_empty());
+    }
+}